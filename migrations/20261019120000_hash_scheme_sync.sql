@@ -0,0 +1,3 @@
+-- Keep the hash_scheme enum in sync with lib_hash::hash_scheme::HashScheme
+
+ALTER TYPE hash_scheme ADD VALUE IF NOT EXISTS 'argon2_v02';
@@ -0,0 +1,3 @@
+-- Configurable argon2 scheme, its params are stored in each PHC hash string
+
+ALTER TYPE hash_scheme ADD VALUE IF NOT EXISTS 'argon2';
@@ -0,0 +1,5 @@
+-- Verify-only schemes for users imported from other apps
+
+ALTER TYPE hash_scheme ADD VALUE IF NOT EXISTS 'bcrypt';
+ALTER TYPE hash_scheme ADD VALUE IF NOT EXISTS 'pbkdf2_sha256';
+ALTER TYPE hash_scheme ADD VALUE IF NOT EXISTS 'scrypt';
@@ -0,0 +1,3 @@
+-- Id of the server-side pepper mixed into pwd_hash, NULL for unpeppered hashes
+
+ALTER TABLE user_management.users ADD COLUMN IF NOT EXISTS pepper_id smallint DEFAULT NULL;
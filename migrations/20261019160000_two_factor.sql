@@ -0,0 +1,23 @@
+-- Optional TOTP two-factor auth, enabled_at is NULL until the first code is confirmed
+
+CREATE TABLE IF NOT EXISTS user_management.totp (
+    id bigint GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    username varchar(32) NOT NULL UNIQUE REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    secret varchar(64) NOT NULL,
+    last_used_step bigint DEFAULT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    enabled_at timestamp DEFAULT NULL
+);
+
+CREATE TABLE IF NOT EXISTS user_management.recovery_codes (
+    id bigint GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    username varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    code_hash varchar(128) NOT NULL,
+    code_salt varchar(32) NOT NULL,
+    hash_scheme hash_scheme NOT NULL,
+    pepper_id smallint DEFAULT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    used_at timestamp DEFAULT NULL
+);
+
+CREATE INDEX ON user_management.recovery_codes (username);
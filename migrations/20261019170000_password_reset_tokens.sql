@@ -0,0 +1,12 @@
+-- Single use password reset tokens, only the sha256 digest of each token is stored
+
+CREATE TABLE IF NOT EXISTS user_management.password_reset_tokens (
+    id bigint GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    username varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    created_at timestamp NOT NULL DEFAULT now(),
+    expires_at timestamp NOT NULL,
+    used_at timestamp DEFAULT NULL
+);
+
+CREATE INDEX ON user_management.password_reset_tokens (username);
@@ -0,0 +1,7 @@
+-- New accounts start unverified, existing accounts are treated as verified
+
+ALTER TABLE user_management.users
+    ADD COLUMN IF NOT EXISTS email_verified_at timestamp DEFAULT NULL,
+    ADD COLUMN IF NOT EXISTS verification_sent_at timestamp DEFAULT NULL;
+
+UPDATE user_management.users SET email_verified_at = created_at WHERE email_verified_at IS NULL;
@@ -0,0 +1,9 @@
+-- Failed login attempts, keyed by "user:<username>" or "ip:<address>"
+-- Usernames are tracked whether or not they exist so that lockouts do not reveal registered usernames
+
+CREATE TABLE IF NOT EXISTS user_management.login_attempts (
+    key varchar(128) PRIMARY KEY,
+    failures integer NOT NULL DEFAULT 0,
+    last_failure_at timestamp NOT NULL DEFAULT now(),
+    locked_until timestamp DEFAULT NULL
+);
@@ -0,0 +1,15 @@
+-- Personal access tokens for scripts and integrations, only the sha256 digest of each token is stored
+
+CREATE TABLE IF NOT EXISTS user_management.access_tokens (
+    id bigint GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    username varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    name varchar(64) NOT NULL,
+    token_hash varchar(64) NOT NULL UNIQUE,
+    scopes text[] NOT NULL DEFAULT '{}',
+    created_at timestamp NOT NULL DEFAULT now(),
+    expires_at timestamp DEFAULT NULL,
+    last_used_at timestamp DEFAULT NULL,
+    revoked_at timestamp DEFAULT NULL
+);
+
+CREATE INDEX ON user_management.access_tokens (username);
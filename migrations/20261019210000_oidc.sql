@@ -0,0 +1,24 @@
+-- Pending OpenID Connect logins, consumed by the callback
+
+CREATE TABLE IF NOT EXISTS user_management.oidc_login_states (
+    state varchar(64) PRIMARY KEY,
+    code_verifier varchar(128) NOT NULL,
+    nonce varchar(64) NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    expires_at timestamp NOT NULL
+);
+
+-- Links an identity at an OpenID Connect provider to a user
+
+CREATE TABLE IF NOT EXISTS user_management.oidc_identities (
+    id bigint GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    issuer varchar(255) NOT NULL,
+    subject varchar(255) NOT NULL,
+    username varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    email varchar(255) DEFAULT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    last_login_at timestamp NOT NULL DEFAULT now(),
+    UNIQUE (issuer, subject)
+);
+
+CREATE INDEX ON user_management.oidc_identities (username);
@@ -0,0 +1,9 @@
+-- Moderation and admin roles granted to users
+
+CREATE TABLE IF NOT EXISTS user_management.user_roles (
+    username varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    role varchar(32) NOT NULL CHECK (role IN ('moderator', 'admin')),
+    granted_by varchar(32) DEFAULT NULL REFERENCES user_management.users (username) ON DELETE SET NULL ON UPDATE CASCADE,
+    granted_at timestamp NOT NULL DEFAULT now(),
+    PRIMARY KEY (username, role)
+);
@@ -0,0 +1,6 @@
+-- Suspensions reuse deactivated_at as the start of the suspension.
+-- A suspension is active while deactivated_at is set and suspended_until is NULL (permanent) or in the future.
+
+ALTER TABLE user_management.users
+    ADD COLUMN IF NOT EXISTS suspended_until timestamp DEFAULT NULL,
+    ADD COLUMN IF NOT EXISTS suspension_reason varchar(255) DEFAULT NULL;
@@ -0,0 +1,10 @@
+-- Saved recommender embeddings, so that restarts do not retrain from scratch
+
+CREATE SCHEMA IF NOT EXISTS recommender;
+
+CREATE TABLE IF NOT EXISTS recommender.snapshots (
+    id bigint GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    version integer NOT NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    data bytea NOT NULL
+);
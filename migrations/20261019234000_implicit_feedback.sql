@@ -0,0 +1,12 @@
+-- Implicit feedback for the recommender, see RECOMMENDER_CHANNELS.
+
+CREATE TABLE IF NOT EXISTS user_management.profile_visits (
+    visitor varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    visited varchar(32) NOT NULL REFERENCES user_management.users (username) ON DELETE CASCADE ON UPDATE CASCADE,
+    visits int NOT NULL DEFAULT 1,
+    last_visited_at timestamp NOT NULL DEFAULT now(),
+    PRIMARY KEY (visitor, visited)
+);
+
+ALTER TABLE post_management.seen_posts
+    ADD COLUMN IF NOT EXISTS dwell_ms bigint NOT NULL DEFAULT 0;
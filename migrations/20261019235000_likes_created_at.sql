@@ -0,0 +1,4 @@
+-- Likes made before this migration share its time, ids still order them.
+
+ALTER TABLE post_management.likes
+    ADD COLUMN IF NOT EXISTS created_at timestamp NOT NULL DEFAULT now();
@@ -0,0 +1,7 @@
+-- When a password reset was last requested for each email, to rate limit reset emails
+-- Emails are tracked whether or not they are registered so that the limit does not reveal registered emails
+
+CREATE TABLE IF NOT EXISTS user_management.password_reset_requests (
+    email varchar(255) PRIMARY KEY,
+    requested_at timestamp NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+-- PHC salts of imported pbkdf2 and scrypt hashes can be up to 64 characters.
+
+ALTER TABLE user_management.users
+    ALTER COLUMN pwd_salt TYPE varchar(64);
@@ -0,0 +1,4 @@
+-- Session jwts issued before this time are rejected, set when the password is changed or reset.
+
+ALTER TABLE user_management.users
+    ADD COLUMN IF NOT EXISTS sessions_valid_after timestamp DEFAULT NULL;
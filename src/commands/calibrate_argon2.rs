@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use lib_hash::hashers::argon2_configurable::Argon2Params;
+
+const DEFAULT_TARGET_MS: u64 = 500;
+const DEFAULT_P_COST: u32 = 1;
+
+/// Benchmarks this host and prints argon2 params hitting the target verification time.
+/// Usage: `calibrate-argon2 [target_ms] [parallelism]`
+pub fn run(args: &[String]) {
+    let target_ms = args
+        .first()
+        .map(|a| a.parse().expect("target_ms must be a number"))
+        .unwrap_or(DEFAULT_TARGET_MS);
+    let p_cost = args
+        .get(1)
+        .map(|a| a.parse().expect("parallelism must be a number"))
+        .unwrap_or(DEFAULT_P_COST);
+
+    let params = Argon2Params::calibrate(Duration::from_millis(target_ms), p_cost)
+        .expect("Could not calibrate argon2 params");
+    let elapsed = params
+        .benchmark()
+        .expect("Could not benchmark argon2 params");
+
+    println!(
+        "# {}ms per hash (target {}ms)",
+        elapsed.as_millis(),
+        target_ms
+    );
+    println!("ARGON2_M_COST={}", params.m_cost);
+    println!("ARGON2_T_COST={}", params.t_cost);
+    println!("ARGON2_P_COST={}", params.p_cost);
+}
@@ -0,0 +1,39 @@
+use crate::services::{recommender_eval::EvaluationData, recommenders::RecommenderConfig};
+
+const DEFAULT_K: usize = 10;
+const DEFAULT_TEST_FRACTION: f64 = 0.2;
+
+/// Trains the recommender with the RECOMMENDER_* env config on all but the newest likes and
+/// reports how well it ranks the held out ones, next to random and popularity baselines.
+/// Usage: `evaluate [k] [test_fraction] [output.json]`, the report is printed when no file is given.
+pub async fn run(args: &[String]) {
+    let k = args
+        .first()
+        .map(|a| a.parse().expect("k must be a number"))
+        .unwrap_or(DEFAULT_K);
+    let test_fraction = args
+        .get(1)
+        .map(|a| a.parse().expect("test_fraction must be a number"))
+        .unwrap_or(DEFAULT_TEST_FRACTION);
+    if k == 0 || test_fraction <= 0.0 || test_fraction >= 1.0 {
+        eprintln!("k must be positive and test_fraction between 0 and 1");
+        std::process::exit(1);
+    }
+
+    let pool = crate::create_pool().await;
+    crate::run_migrations(&pool).await;
+
+    let data = EvaluationData::load(&pool, test_fraction)
+        .await
+        .expect("Could not load interactions");
+    let report = data.evaluate(RecommenderConfig::from_env(), k, test_fraction);
+    let json = serde_json::to_string_pretty(&report).expect("Could not serialize report");
+
+    match args.get(2) {
+        Some(path) => {
+            std::fs::write(path, json).expect("Could not write report");
+            println!("Wrote evaluation report to {}", path);
+        }
+        None => println!("{}", json),
+    }
+}
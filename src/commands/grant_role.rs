@@ -0,0 +1,38 @@
+use ctx::Role;
+
+use crate::models::{role_model::grant_role, user_model::get_user_id};
+
+/// Grants a role from the command line, for creating the first admin.
+/// Usage: `grant-role <username> <moderator|admin>`
+pub async fn run(args: &[String]) {
+    let (Some(username), Some(role)) = (args.first(), args.get(1)) else {
+        eprintln!("Usage: grant-role <username> <moderator|admin>");
+        std::process::exit(1);
+    };
+    let role = role.parse::<Role>().unwrap_or_else(|_| {
+        eprintln!("Unknown role: {}", role);
+        std::process::exit(1);
+    });
+
+    let pool = crate::create_pool().await;
+    crate::run_migrations(&pool).await;
+
+    let username = username.trim().to_lowercase();
+    if get_user_id(&username, &pool)
+        .await
+        .expect("Could not look up user")
+        .is_none()
+    {
+        eprintln!("Unknown user: {}", username);
+        std::process::exit(1);
+    }
+
+    let granted = grant_role(&pool, &username, role.as_str(), None)
+        .await
+        .expect("Could not grant role");
+    if granted == 0 {
+        println!("{} already has the {} role", username, role.as_str());
+    } else {
+        println!("Granted the {} role to {}", role.as_str(), username);
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+
+use lib_hash::hash_scheme::HashScheme;
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    libs::validation::{RE_NAME, RE_USERNAME},
+    models::{
+        base,
+        user_model::{username_or_email_exists, CreateUserModel, UserModel},
+    },
+};
+
+/// A user exported from another app, one JSON object per line.
+/// `pwd_hash` is the full hash string, with its salt embedded, in the format of `hash_scheme`.
+#[derive(Deserialize, Validate)]
+struct ImportUserModel {
+    #[validate(length(min = 1, max = 32, message = "Invalid username length"))]
+    #[validate(regex(path = "*RE_USERNAME", message = "Invalid username"))]
+    username: String,
+    #[validate(
+        email(message = "Invalid email"),
+        length(min = 1, max = 255, message = "Invalid email length")
+    )]
+    email: String,
+    #[validate(length(min = 1, max = 32, message = "Invalid first name length"))]
+    #[validate(regex(path = "*RE_NAME"))]
+    first_name: String,
+    #[validate(length(min = 1, max = 32, message = "Invalid last name length"))]
+    #[validate(regex(path = "*RE_NAME", message = "Invalid last name"))]
+    last_name: String,
+    hash_scheme: HashScheme,
+    #[validate(length(min = 1, max = 128, message = "Invalid hash length"))]
+    pwd_hash: String,
+}
+
+/// Bulk imports users with their existing password hashes, e.g. bcrypt or PBKDF2.
+/// They are rehashed with `HashScheme::CURRENT` on their next login.
+/// Usage: `import-users <users.jsonl>`
+pub async fn run(args: &[String]) {
+    let path = args.first().expect("Usage: import-users <users.jsonl>");
+    let contents = fs::read_to_string(path).expect("Could not read import file");
+
+    let pool = crate::create_pool().await;
+    crate::run_migrations(&pool).await;
+
+    let mut imported = 0;
+    let mut skipped = 0;
+
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_num = i + 1;
+
+        let mut user = match serde_json::from_str::<ImportUserModel>(line) {
+            Ok(u) => u,
+            Err(e) => {
+                eprintln!("line {}: invalid json: {}", line_num, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        if let Err(e) = user.validate() {
+            eprintln!("line {}: {}", line_num, e);
+            skipped += 1;
+            continue;
+        }
+
+        user.username = user.username.trim().to_lowercase();
+
+        let taken = username_or_email_exists(&user.username, &user.email, &pool)
+            .await
+            .expect("Could not query users");
+        if let Some(taken) = taken {
+            eprintln!("line {}: {} already taken", line_num, taken);
+            skipped += 1;
+            continue;
+        }
+
+        let pwd_salt = match user.hash_scheme.hasher().salt_from_hash(&user.pwd_hash) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!(
+                    "line {}: invalid {:?} hash: {:?}",
+                    line_num, user.hash_scheme, e
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let create_model = CreateUserModel {
+            username: user.username,
+            email: user.email,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            pwd_hash: user.pwd_hash,
+            pwd_salt,
+            hash_scheme: user.hash_scheme,
+            pepper_id: None,
+        };
+
+        match base::create::<UserModel, _>(create_model, &pool).await {
+            Ok(_) => imported += 1,
+            Err(e) => {
+                eprintln!("line {}: {:?}", line_num, e);
+                skipped += 1;
+            }
+        }
+    }
+
+    println!("Imported {} users, skipped {}", imported, skipped);
+}
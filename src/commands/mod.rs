@@ -0,0 +1,20 @@
+mod calibrate_argon2;
+mod evaluate;
+mod grant_role;
+mod import_users;
+mod tune;
+
+/// Runs a CLI subcommand instead of the server, e.g. `flex-forum-back-end calibrate-argon2 500`.
+pub async fn run(command: &str, args: &[String]) {
+    match command {
+        "calibrate-argon2" => calibrate_argon2::run(args),
+        "import-users" => import_users::run(args).await,
+        "grant-role" => grant_role::run(args).await,
+        "evaluate" => evaluate::run(args).await,
+        "tune" => tune::run(args).await,
+        _ => {
+            eprintln!("Unknown command: {}", command);
+            std::process::exit(1);
+        }
+    }
+}
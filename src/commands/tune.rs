@@ -0,0 +1,182 @@
+use itertools::iproduct;
+use rand::Rng;
+
+use crate::services::{
+    recommender_eval::{EvaluationData, RankingMetrics},
+    recommenders::{RecommenderConfig, RecommenderKind},
+};
+
+const DEFAULT_TRIALS: usize = 30;
+const DEFAULT_MAX_EPOCHS: usize = 50;
+/// Epochs without a lower validation loss before a trial stops.
+const PATIENCE: usize = 3;
+const K: usize = 10;
+const VALIDATION_FRACTION: f64 = 0.2;
+const TEST_FRACTION: f64 = 0.2;
+const VALIDATION_NEGATIVE_RATIO: usize = 4;
+
+const GRID_K_FEATURES: [usize; 4] = [5, 10, 20, 40];
+const GRID_ALPHA: [f32; 4] = [0.01, 0.02, 0.05, 0.1];
+const GRID_LAMBDA: [f32; 4] = [0.01, 0.05, 0.1, 0.2];
+
+struct Trial {
+    config: RecommenderConfig,
+    validation_loss: f32,
+    metrics: RankingMetrics,
+}
+
+/// Searches k_features, alpha and lambda, splitting the likes by time into train, validation and test.
+/// Each trial trains on the train likes, stopping early once the loss on the validation likes stops improving,
+/// and the config with the best validation NDCG is printed as env lines. Only that config is then retrained on
+/// train and validation and reported on the test likes, so the reported metrics are not tuned on.
+/// Only matrix factorization is tuned, the other RECOMMENDER_* env values are kept.
+/// Progress lines start with # so the output can go in a .env.
+/// Usage: `tune [grid|random] [trials] [max_epochs]`, trials only apply to random search.
+pub async fn run(args: &[String]) {
+    let mode = args.first().map(String::as_str).unwrap_or("random");
+    let trials = args
+        .get(1)
+        .map(|a| a.parse().expect("trials must be a number"))
+        .unwrap_or(DEFAULT_TRIALS);
+    let max_epochs = args
+        .get(2)
+        .map(|a| a.parse().expect("max_epochs must be a number"))
+        .unwrap_or(DEFAULT_MAX_EPOCHS);
+
+    let base = RecommenderConfig {
+        kind: RecommenderKind::MatrixFactorization,
+        ..RecommenderConfig::from_env()
+    };
+    let configs = match mode {
+        "grid" => grid_configs(&base),
+        "random" => random_configs(&base, trials),
+        _ => {
+            eprintln!("Usage: tune [grid|random] [trials] [max_epochs]");
+            std::process::exit(1);
+        }
+    };
+
+    let pool = crate::create_pool().await;
+    crate::run_migrations(&pool).await;
+
+    let (data, test_data) =
+        EvaluationData::load_with_validation(&pool, VALIDATION_FRACTION, TEST_FRACTION)
+            .await
+            .expect("Could not load interactions");
+    if data.held_out_likes() == 0 || test_data.held_out_likes() == 0 {
+        eprintln!("Not enough likes to hold out validation and test likes");
+        std::process::exit(1);
+    }
+    let validation = data.validation_pairs(VALIDATION_NEGATIVE_RATIO);
+    let popularity = data.evaluate_popularity(K);
+    println!(
+        "# {} trials, popularity baseline validation ndcg@{} {:.4}",
+        configs.len(),
+        K,
+        popularity.ndcg_at_k
+    );
+
+    let mut best: Option<Trial> = None;
+    for (i, mut config) in configs.into_iter().enumerate() {
+        let (model, epochs, validation_loss) =
+            data.train_with_early_stopping(config.clone(), &validation, max_epochs, PATIENCE);
+        config.epochs = epochs;
+        let metrics = data.rank_model(&model, K);
+        println!(
+            "# {}: k_features {} alpha {} lambda {} epochs {}, loss {:.4}, ndcg@{} {:.4}",
+            i + 1,
+            config.k_features,
+            config.alpha,
+            config.lambda,
+            epochs,
+            validation_loss,
+            K,
+            metrics.ndcg_at_k
+        );
+
+        let trial = Trial {
+            config,
+            validation_loss,
+            metrics,
+        };
+        if best.as_ref().is_none_or(|b| is_better(&trial, b)) {
+            best = Some(trial);
+        }
+    }
+
+    let Some(best) = best else {
+        eprintln!("No trials were run");
+        std::process::exit(1);
+    };
+    println!(
+        "# best validation ndcg@{} {:.4}, validation loss {:.4}",
+        K, best.metrics.ndcg_at_k, best.validation_loss
+    );
+
+    let config = RecommenderConfig {
+        epochs: best.config.epochs.max(1),
+        ..best.config.clone()
+    };
+    let test = test_data.evaluate_model(config, K);
+    let test_popularity = test_data.evaluate_popularity(K);
+    println!(
+        "# test ndcg@{} {:.4}, precision@{} {:.4}, recall@{} {:.4}, popularity baseline ndcg@{} {:.4}",
+        K,
+        test.ndcg_at_k,
+        K,
+        test.precision_at_k,
+        K,
+        test.recall_at_k,
+        K,
+        test_popularity.ndcg_at_k
+    );
+    println!("RECOMMENDER=matrix_factorization");
+    println!("RECOMMENDER_K_FEATURES={}", best.config.k_features);
+    println!("RECOMMENDER_ALPHA={}", best.config.alpha);
+    println!("RECOMMENDER_LAMBDA={}", best.config.lambda);
+    println!("RECOMMENDER_EPOCHS={}", best.config.epochs.max(1));
+}
+
+/// Higher validation NDCG wins, ties go to the lower validation loss.
+fn is_better(trial: &Trial, best: &Trial) -> bool {
+    if trial.validation_loss.is_nan() {
+        return false;
+    }
+    match trial.metrics.ndcg_at_k.partial_cmp(&best.metrics.ndcg_at_k) {
+        Some(std::cmp::Ordering::Greater) => true,
+        Some(std::cmp::Ordering::Equal) => {
+            best.validation_loss.is_nan() || trial.validation_loss < best.validation_loss
+        }
+        _ => best.validation_loss.is_nan(),
+    }
+}
+
+fn grid_configs(base: &RecommenderConfig) -> Vec<RecommenderConfig> {
+    iproduct!(GRID_K_FEATURES, GRID_ALPHA, GRID_LAMBDA)
+        .map(|(k_features, alpha, lambda)| RecommenderConfig {
+            k_features,
+            alpha,
+            lambda,
+            ..base.clone()
+        })
+        .collect()
+}
+
+/// Samples alpha and lambda log uniformly, as they matter by order of magnitude.
+fn random_configs(base: &RecommenderConfig, trials: usize) -> Vec<RecommenderConfig> {
+    let mut rng = rand::thread_rng();
+    let mut configs = Vec::with_capacity(trials);
+    for _ in 0..trials {
+        configs.push(RecommenderConfig {
+            k_features: GRID_K_FEATURES[rng.gen_range(0..GRID_K_FEATURES.len())],
+            alpha: log_uniform(&mut rng, 0.005, 0.2),
+            lambda: log_uniform(&mut rng, 0.001, 0.5),
+            ..base.clone()
+        });
+    }
+    configs
+}
+
+fn log_uniform(rng: &mut impl Rng, low: f32, high: f32) -> f32 {
+    10f32.powf(rng.gen_range(low.log10()..high.log10()))
+}
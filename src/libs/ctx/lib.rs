@@ -1,18 +1,230 @@
-use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use std::{marker::PhantomData, str::FromStr};
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, Extensions},
+};
 use jwt::JWT;
 use lib_routes::error::{RouteError, RouterResult};
 
+/// What a personal access token is allowed to do. Sessions are allowed everything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    FeedRead,
+    PostsWrite,
+    InteractionsWrite,
+    ProfileRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::FeedRead,
+        Scope::PostsWrite,
+        Scope::InteractionsWrite,
+        Scope::ProfileRead,
+    ];
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::FeedRead => "feed:read",
+            Scope::PostsWrite => "posts:write",
+            Scope::InteractionsWrite => "interactions:write",
+            Scope::ProfileRead => "profile:read",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = RouteError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or(RouteError::Validation(format!("Invalid scope {}", s)))
+    }
+}
+
+/// A scope that can be required by type, see `require_scope` in the server's middleware.
+pub trait RequiredScope: Send + Sync + 'static {
+    const SCOPE: Scope;
+}
+
+pub struct FeedReadScope;
+pub struct PostsWriteScope;
+pub struct InteractionsWriteScope;
+pub struct ProfileReadScope;
+
+impl RequiredScope for FeedReadScope {
+    const SCOPE: Scope = Scope::FeedRead;
+}
+
+impl RequiredScope for PostsWriteScope {
+    const SCOPE: Scope = Scope::PostsWrite;
+}
+
+impl RequiredScope for InteractionsWriteScope {
+    const SCOPE: Scope = Scope::InteractionsWrite;
+}
+
+impl RequiredScope for ProfileReadScope {
+    const SCOPE: Scope = Scope::ProfileRead;
+}
+
+/// Roles granted to a user for moderating and administering the site. Admins can do everything moderators can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 2] = [Role::Moderator, Role::Admin];
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+    /// Whether holding this role grants `other`.
+    pub fn includes(&self, other: Role) -> bool {
+        *self == other || *self == Role::Admin
+    }
+}
+
+impl FromStr for Role {
+    type Err = RouteError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or(RouteError::Validation(format!("Invalid role {}", s)))
+    }
+}
+
+/// A role that can be required by type, see `HasRole`.
+pub trait RequiredRole: Send + Sync + 'static {
+    const ROLE: Role;
+}
+
+pub struct ModeratorRole;
+pub struct AdminRole;
+
+impl RequiredRole for ModeratorRole {
+    const ROLE: Role = Role::Moderator;
+}
+
+impl RequiredRole for AdminRole {
+    const ROLE: Role = Role::Admin;
+}
+
+#[derive(Clone, Debug)]
+pub enum CtxAuth {
+    Session(JWT),
+    AccessToken { id: i64, scopes: Vec<Scope> },
+}
+
 #[derive(Clone, Debug)]
 pub struct Ctx {
-    jwt: JWT,
+    username: String,
+    auth: CtxAuth,
+    roles: Vec<Role>,
+    /// None when the account no longer exists.
+    user_id: Option<i64>,
+    /// Whether the route declared a scope the access token has, see `grant_scope`.
+    scope_granted: bool,
 }
 
 impl Ctx {
     pub fn new(jwt: JWT) -> Self {
-        Self { jwt }
+        Self {
+            username: jwt.username().to_string(),
+            auth: CtxAuth::Session(jwt),
+            roles: Vec::new(),
+            user_id: None,
+            scope_granted: false,
+        }
     }
-    pub fn jwt(&self) -> &JWT {
-        &self.jwt
+    pub fn from_access_token(username: String, id: i64, scopes: Vec<Scope>) -> Self {
+        Self {
+            username,
+            auth: CtxAuth::AccessToken { id, scopes },
+            roles: Vec::new(),
+            user_id: None,
+            scope_granted: false,
+        }
+    }
+    /// The Ctx inserted by `ctx_resolver`, without the scope check of the extractor,
+    /// for middleware that runs before a route grants its scope.
+    pub fn from_extensions(extensions: &Extensions) -> RouterResult<Self> {
+        extensions
+            .get::<RouterResult<Ctx>>()
+            .ok_or(RouteError::InvalidAuth)?
+            .clone()
+    }
+    pub fn with_roles(mut self, roles: Vec<Role>) -> Self {
+        self.roles = roles;
+        self
+    }
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+    pub fn auth(&self) -> &CtxAuth {
+        &self.auth
+    }
+    /// The session jwt, None when authenticated with an access token.
+    pub fn jwt(&self) -> Option<&JWT> {
+        match &self.auth {
+            CtxAuth::Session(jwt) => Some(jwt),
+            CtxAuth::AccessToken { .. } => None,
+        }
+    }
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match &self.auth {
+            CtxAuth::Session(_) => true,
+            CtxAuth::AccessToken { scopes, .. } => scopes.contains(&scope),
+        }
+    }
+    pub fn with_user_id(mut self, user_id: Option<i64>) -> Self {
+        self.user_id = user_id;
+        self
+    }
+    /// The id of the active account the token belongs to, see `AuthUser`.
+    pub fn user_id(&self) -> Option<i64> {
+        self.user_id
+    }
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.iter().any(|r| r.includes(role))
+    }
+    /// Errs with Forbidden if the user does not have the role.
+    pub fn require_role(&self, role: Role) -> RouterResult<()> {
+        if !self.has_role(role) {
+            return Err(RouteError::Forbidden);
+        }
+        Ok(())
+    }
+    /// Errs with Forbidden if an access token without the scope was used.
+    pub fn require_scope(&self, scope: Scope) -> RouterResult<()> {
+        if !self.has_scope(scope) {
+            return Err(RouteError::Forbidden);
+        }
+        Ok(())
+    }
+    /// Lets an access token with the scope through the Ctx extractor for the rest of the request.
+    /// Errs with Forbidden if an access token without the scope was used.
+    pub fn grant_scope(mut self, scope: Scope) -> RouterResult<Self> {
+        self.require_scope(scope)?;
+        self.scope_granted = true;
+        Ok(self)
+    }
+    /// Errs with Forbidden for access tokens, for account management that needs a logged in user.
+    pub fn require_session(&self) -> RouterResult<()> {
+        if self.jwt().is_none() {
+            return Err(RouteError::Forbidden);
+        }
+        Ok(())
     }
 }
 
@@ -21,11 +233,53 @@ impl<S: Send + Sync> FromRequestParts<S> for Ctx {
     type Rejection = RouteError;
 
     #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    /// Rejects access tokens with Forbidden unless the route granted a scope, so that routes
+    /// which do not declare one are limited to sessions.
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> RouterResult<Self> {
-        parts
-            .extensions
-            .get::<RouterResult<Ctx>>()
-            .ok_or(RouteError::InvalidAuth)?
-            .clone()
+        let ctx = Ctx::from_extensions(&parts.extensions)?;
+        if matches!(ctx.auth, CtxAuth::AccessToken { .. }) && !ctx.scope_granted {
+            return Err(RouteError::Forbidden);
+        }
+        Ok(ctx)
+    }
+}
+
+/// An authenticated request from an account that still exists.
+/// Rejects with Unauthorized for tokens whose account was deleted after they were issued.
+#[derive(Clone, Debug)]
+pub struct AuthUser {
+    pub id: i64,
+    pub ctx: Ctx,
+}
+
+impl TryFrom<Ctx> for AuthUser {
+    type Error = RouteError;
+    fn try_from(ctx: Ctx) -> RouterResult<Self> {
+        let id = ctx.user_id().ok_or(RouteError::Unauthorized)?;
+        Ok(AuthUser { id, ctx })
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = RouteError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> RouterResult<Self> {
+        Ctx::from_request_parts(parts, state).await?.try_into()
+    }
+}
+
+/// Extracts the Ctx only if the user has the role `R`, for requiring a role on a single handler.
+/// e.g. `async fn handler(HasRole(ctx, _): HasRole<AdminRole>)`
+pub struct HasRole<R: RequiredRole>(pub Ctx, pub PhantomData<R>);
+
+#[async_trait]
+impl<S: Send + Sync, R: RequiredRole> FromRequestParts<S> for HasRole<R> {
+    type Rejection = RouteError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> RouterResult<Self> {
+        let ctx = Ctx::from_request_parts(parts, state).await?;
+        ctx.require_role(R::ROLE)?;
+        Ok(HasRole(ctx, PhantomData))
     }
 }
@@ -0,0 +1,16 @@
+use std::{env, fmt::Debug, str::FromStr};
+
+/// Parses the env var `key`, returning `default` if it is not set.
+/// Panics if the var is set but cannot be parsed.
+pub fn env_or<T>(key: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: Debug,
+{
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("Invalid {}: {:?}", key, e)),
+        Err(_) => default,
+    }
+}
@@ -19,9 +19,11 @@ pub struct JWT {
 impl JWT {
     const JWT_HASH_SCHEME: HashScheme = HashScheme::Argon2V02;
     pub fn new(username: String, key: &str) -> JWTResult<JWT> {
-        let expires = Utc::now()
-            .checked_add_signed(TimeDelta::minutes(JWT_LIFE_IN_MINUTES))
-            .unwrap();
+        Self::with_lifetime(username, key, TimeDelta::minutes(JWT_LIFE_IN_MINUTES))
+    }
+    /// Creates a signed jwt that expires after `lifetime` instead of the default.
+    pub fn with_lifetime(username: String, key: &str, lifetime: TimeDelta) -> JWTResult<JWT> {
+        let expires = Utc::now().checked_add_signed(lifetime).unwrap();
 
         let mut jwt = JWT {
             username,
@@ -3,9 +3,14 @@ pub type HashResult<T> = Result<T, HashError>;
 #[derive(Debug)]
 pub enum HashError {
     Argon2Error(argon2::password_hash::Error),
+    AlreadyConfigured,
+    BcryptError(bcrypt::BcryptError),
     InvalidLength,
     MacError,
+    UnknownPepper(i16),
     VerificationFail,
+    /// The scheme can only verify existing hashes, not create new ones.
+    VerifyOnly,
 }
 
 impl From<argon2::password_hash::Error> for HashError {
@@ -14,6 +19,18 @@ impl From<argon2::password_hash::Error> for HashError {
     }
 }
 
+impl From<argon2::Error> for HashError {
+    fn from(value: argon2::Error) -> Self {
+        HashError::Argon2Error(value.into())
+    }
+}
+
+impl From<bcrypt::BcryptError> for HashError {
+    fn from(value: bcrypt::BcryptError) -> Self {
+        HashError::BcryptError(value)
+    }
+}
+
 impl From<sha2::digest::InvalidLength> for HashError {
     fn from(_value: sha2::digest::InvalidLength) -> Self {
         Self::InvalidLength
@@ -1,12 +1,19 @@
+use argon2::PasswordHash;
 use serde::{self, Deserialize, Serialize};
 use sqlb::SqlxBindable;
 
 use crate::{
-    error::HashResult,
-    hashers::{argon2_v01::Argon2V01, argon2_v02::Argon2V02},
+    error::{HashError, HashResult},
+    hashers::{
+        argon2_configurable::Argon2Configurable, argon2_v01::Argon2V01, argon2_v02::Argon2V02,
+        legacy_bcrypt::LegacyBcrypt, legacy_pbkdf2::LegacyPbkdf2Sha256,
+        legacy_scrypt::LegacyScrypt,
+    },
+    pepper::Pepper,
 };
 
-#[derive(sqlx::Type, Debug, Serialize, Deserialize)]
+/// Mirrors the `hash_scheme` Postgres enum. Every variant added here needs a migration adding it to the DB enum.
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[sqlx(type_name = "hash_scheme")]
 pub enum HashScheme {
     #[sqlx(rename = "argon2_v01")]
@@ -15,13 +22,37 @@ pub enum HashScheme {
     #[sqlx(rename = "argon2_v02")]
     #[serde(rename(serialize = "argon2_v02", deserialize = "argon2_v02"))]
     Argon2V02,
+    #[sqlx(rename = "argon2")]
+    #[serde(rename(serialize = "argon2", deserialize = "argon2"))]
+    Argon2,
+    #[sqlx(rename = "bcrypt")]
+    #[serde(rename(serialize = "bcrypt", deserialize = "bcrypt"))]
+    Bcrypt,
+    #[sqlx(rename = "pbkdf2_sha256")]
+    #[serde(rename(serialize = "pbkdf2_sha256", deserialize = "pbkdf2_sha256"))]
+    Pbkdf2Sha256,
+    #[sqlx(rename = "scrypt")]
+    #[serde(rename(serialize = "scrypt", deserialize = "scrypt"))]
+    Scrypt,
 }
 
 impl HashScheme {
+    /// The scheme new password hashes are created with.
+    /// Hashes stored with any other scheme are rehashed with this one on the user's next login.
+    pub const CURRENT: HashScheme = HashScheme::Argon2;
+
+    /// Returns true if hashes of this scheme should be recomputed with `HashScheme::CURRENT`.
+    pub fn needs_rehash(&self) -> bool {
+        *self != Self::CURRENT
+    }
     pub fn hasher(&self) -> Box<dyn Hasher> {
         match self {
             HashScheme::Argon2V01 => Box::new(Argon2V01),
             HashScheme::Argon2V02 => Box::new(Argon2V02),
+            HashScheme::Argon2 => Box::new(Argon2Configurable),
+            HashScheme::Bcrypt => Box::new(LegacyBcrypt),
+            HashScheme::Pbkdf2Sha256 => Box::new(LegacyPbkdf2Sha256),
+            HashScheme::Scrypt => Box::new(LegacyScrypt),
         }
     }
 }
@@ -38,7 +69,7 @@ impl SqlxBindable for HashScheme {
 pub type Salt = String;
 pub type Hash = String;
 
-pub trait Hasher {
+pub trait Hasher: Send + Sync {
     fn hash_prefix(&self) -> &'static str;
     /// Generic function that hashes a password based upon a hash_scheme, returning the hash and salt string.
     fn hash(&self, password: &str) -> HashResult<(Hash, Salt)>;
@@ -46,4 +77,39 @@ pub trait Hasher {
     fn hash_with_salt(&self, password: &str, salt: &str) -> HashResult<Hash>;
     /// Generic function that validates a password, returning Ok(()) if valid.
     fn verify(&self, password: &str, salt_str: &Salt, hash_str: &Hash) -> HashResult<()>;
+    /// Hashes a password mixed with the given pepper, or unpeppered if None.
+    fn hash_peppered(&self, password: &str, pepper: Option<&Pepper>) -> HashResult<(Hash, Salt)> {
+        match pepper {
+            Some(p) => self.hash(&p.apply(password)?),
+            None => self.hash(password),
+        }
+    }
+    /// Validates a password against a hash made with the given pepper, or an unpeppered hash if None.
+    fn verify_peppered(
+        &self,
+        password: &str,
+        pepper: Option<&Pepper>,
+        salt_str: &Salt,
+        hash_str: &Hash,
+    ) -> HashResult<()> {
+        match pepper {
+            Some(p) => self.verify(&p.apply(password)?, salt_str, hash_str),
+            None => self.verify(password, salt_str, hash_str),
+        }
+    }
+    /// Returns true if a hash made by this scheme should be recomputed, e.g. because its cost params changed.
+    fn hash_outdated(&self, _hash_str: &Hash) -> bool {
+        false
+    }
+    /// Extracts the salt embedded in a hash string, for hashes made outside of this app.
+    fn salt_from_hash(&self, hash_str: &Hash) -> HashResult<Salt> {
+        let salt = PasswordHash::new(hash_str)?
+            .salt
+            .ok_or(HashError::Argon2Error(
+                argon2::password_hash::Error::SaltInvalid(
+                    argon2::password_hash::errors::InvalidValue::Malformed,
+                ),
+            ))?;
+        Ok(salt.to_string())
+    }
 }
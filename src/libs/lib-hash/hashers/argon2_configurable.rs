@@ -0,0 +1,187 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, PasswordVerifier, Salt, SaltString},
+    Argon2, PasswordHash,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{HashError, HashResult},
+    hash_scheme::{HashScheme, Hasher},
+};
+
+/// The configured params together with the hash prefix they produce, so the two cannot disagree.
+static CONFIGURED: OnceLock<(Argon2Params, String)> = OnceLock::new();
+
+/// Argon2id cost parameters. Memory is in KiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Argon2Params {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self {
+            m_cost: argon2::Params::DEFAULT_M_COST,
+            t_cost: argon2::Params::DEFAULT_T_COST,
+            p_cost: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Upper bound on memory used while calibrating (1 GiB).
+    const CALIBRATION_MAX_M_COST: u32 = 1024 * 1024;
+    const CALIBRATION_MAX_T_COST: u32 = 16;
+
+    fn argon2<'key>(&self) -> HashResult<Argon2<'key>> {
+        let params = argon2::Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))?;
+        Ok(Argon2::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            params,
+        ))
+    }
+
+    /// Reads the params back out of a stored PHC hash string.
+    pub fn from_hash(hash_str: &str) -> HashResult<Argon2Params> {
+        let hash = PasswordHash::new(hash_str)?;
+        let params = argon2::Params::try_from(&hash)?;
+        Ok(Self {
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+        })
+    }
+
+    /// Times a single hash with these params on this host.
+    pub fn benchmark(&self) -> HashResult<Duration> {
+        let argon2 = self.argon2()?;
+        let salt = SaltString::generate(&mut OsRng);
+        let start = Instant::now();
+        argon2.hash_password(b"calibration password", &salt)?;
+        Ok(start.elapsed())
+    }
+
+    /// Benchmarks this host and returns the most expensive params whose hash time stays within `target`.
+    /// Memory is doubled first, then passes are added once the memory cap is reached.
+    /// Never returns params cheaper than the defaults.
+    pub fn calibrate(target: Duration, p_cost: u32) -> HashResult<Argon2Params> {
+        let mut best = Self {
+            p_cost,
+            ..Self::default()
+        };
+        let mut candidate = best;
+
+        loop {
+            // take the fastest of a few runs to reduce noise from the rest of the host
+            let elapsed = (0..3)
+                .map(|_| candidate.benchmark())
+                .collect::<HashResult<Vec<_>>>()?
+                .into_iter()
+                .min()
+                .unwrap_or_default();
+            if elapsed > target {
+                break;
+            }
+            best = candidate;
+
+            if candidate.m_cost * 2 <= Self::CALIBRATION_MAX_M_COST {
+                candidate.m_cost *= 2;
+            } else if candidate.t_cost < Self::CALIBRATION_MAX_T_COST {
+                candidate.t_cost += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok(best)
+    }
+
+    fn hash_prefix(&self) -> String {
+        format!(
+            "$argon2id$v=19$m={},t={},p={}$",
+            self.m_cost, self.t_cost, self.p_cost
+        )
+    }
+}
+
+/// Sets the params used for new hashes. Must be called before the first hash is made.
+pub fn configure(params: Argon2Params) -> HashResult<()> {
+    // validates the params before anything is hashed with them
+    params.argon2()?;
+    CONFIGURED
+        .set((params, params.hash_prefix()))
+        .or(Err(HashError::AlreadyConfigured))
+}
+
+fn configured() -> &'static (Argon2Params, String) {
+    CONFIGURED.get_or_init(|| {
+        let params = Argon2Params::default();
+        (params, params.hash_prefix())
+    })
+}
+
+/// The params new hashes are created with, falling back to the defaults if never configured.
+pub fn params() -> Argon2Params {
+    configured().0
+}
+
+/// Argon2id hasher whose cost comes from configuration rather than being hardcoded.
+/// The params are recorded in each PHC hash string, so hashes made with older params keep verifying.
+pub struct Argon2Configurable;
+
+impl Hasher for Argon2Configurable {
+    fn hash_prefix(&self) -> &'static str {
+        &configured().1
+    }
+    fn hash(
+        &self,
+        password: &str,
+    ) -> HashResult<(crate::hash_scheme::Hash, crate::hash_scheme::Salt)> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = params()
+            .argon2()?
+            .hash_password(password.as_bytes(), &salt)?;
+        Ok((hash.to_string(), salt.to_string()))
+    }
+
+    fn hash_with_salt(&self, password: &str, salt: &str) -> HashResult<crate::hash_scheme::Hash> {
+        let salt = SaltString::from_b64(salt)?;
+        let hash = params()
+            .argon2()?
+            .hash_password(password.as_bytes(), &salt)?;
+        Ok(hash.to_string())
+    }
+
+    fn verify(
+        &self,
+        password: &str,
+        salt_str: &crate::hash_scheme::Salt,
+        hash_str: &crate::hash_scheme::Hash,
+    ) -> HashResult<()> {
+        let mut pwd_hash = PasswordHash::new(hash_str)?;
+        pwd_hash.salt = Some(Salt::from_b64(salt_str)?);
+        // verify_password uses the params stored in the hash, not the configured ones
+        Argon2::default()
+            .verify_password(password.as_bytes(), &pwd_hash)
+            .or(Err(HashError::VerificationFail))?;
+        Ok(())
+    }
+
+    fn hash_outdated(&self, hash_str: &crate::hash_scheme::Hash) -> bool {
+        Argon2Params::from_hash(hash_str).map_or(true, |p| p != params())
+    }
+}
+
+impl From<Argon2Configurable> for HashScheme {
+    fn from(_value: Argon2Configurable) -> Self {
+        HashScheme::Argon2
+    }
+}
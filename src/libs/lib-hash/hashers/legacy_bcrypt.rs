@@ -0,0 +1,59 @@
+use crate::{
+    error::{HashError, HashResult},
+    hash_scheme::{HashScheme, Hasher},
+};
+
+/// Verify-only bcrypt hasher for users imported from other apps.
+/// They are rehashed with `HashScheme::CURRENT` on their next login.
+pub struct LegacyBcrypt;
+
+impl LegacyBcrypt {
+    /// `$2b$<cost>$` is followed by the 22 character salt and then the hash.
+    const SALT_START: usize = 7;
+    const SALT_LEN: usize = 22;
+}
+
+impl Hasher for LegacyBcrypt {
+    fn hash_prefix(&self) -> &'static str {
+        "$2b$"
+    }
+    fn hash(
+        &self,
+        _password: &str,
+    ) -> HashResult<(crate::hash_scheme::Hash, crate::hash_scheme::Salt)> {
+        Err(HashError::VerifyOnly)
+    }
+
+    fn hash_with_salt(&self, _password: &str, _salt: &str) -> HashResult<crate::hash_scheme::Hash> {
+        Err(HashError::VerifyOnly)
+    }
+
+    /// The salt is embedded in the bcrypt hash, so `_salt_str` is ignored.
+    fn verify(
+        &self,
+        password: &str,
+        _salt_str: &crate::hash_scheme::Salt,
+        hash_str: &crate::hash_scheme::Hash,
+    ) -> HashResult<()> {
+        if !bcrypt::verify(password, hash_str)? {
+            return Err(HashError::VerificationFail);
+        }
+        Ok(())
+    }
+
+    fn salt_from_hash(
+        &self,
+        hash_str: &crate::hash_scheme::Hash,
+    ) -> HashResult<crate::hash_scheme::Salt> {
+        hash_str
+            .get(Self::SALT_START..Self::SALT_START + Self::SALT_LEN)
+            .map(|s| s.to_string())
+            .ok_or(HashError::InvalidLength)
+    }
+}
+
+impl From<LegacyBcrypt> for HashScheme {
+    fn from(_value: LegacyBcrypt) -> Self {
+        HashScheme::Bcrypt
+    }
+}
@@ -0,0 +1,52 @@
+use argon2::{password_hash::PasswordVerifier, PasswordHash};
+use pbkdf2::{Algorithm, Pbkdf2};
+
+use crate::{
+    error::{HashError, HashResult},
+    hash_scheme::{HashScheme, Hasher},
+};
+
+/// Verify-only PBKDF2-SHA256 hasher for users imported from other apps.
+/// Expects PHC strings, e.g. `$pbkdf2-sha256$i=600000,l=32$<salt>$<hash>`.
+pub struct LegacyPbkdf2Sha256;
+
+impl Hasher for LegacyPbkdf2Sha256 {
+    fn hash_prefix(&self) -> &'static str {
+        "$pbkdf2-sha256$"
+    }
+    fn hash(
+        &self,
+        _password: &str,
+    ) -> HashResult<(crate::hash_scheme::Hash, crate::hash_scheme::Salt)> {
+        Err(HashError::VerifyOnly)
+    }
+
+    fn hash_with_salt(&self, _password: &str, _salt: &str) -> HashResult<crate::hash_scheme::Hash> {
+        Err(HashError::VerifyOnly)
+    }
+
+    /// The salt is embedded in the PHC string, so `_salt_str` is ignored.
+    fn verify(
+        &self,
+        password: &str,
+        _salt_str: &crate::hash_scheme::Salt,
+        hash_str: &crate::hash_scheme::Hash,
+    ) -> HashResult<()> {
+        let pwd_hash = PasswordHash::new(hash_str)?;
+        if pwd_hash.algorithm != Algorithm::Pbkdf2Sha256.ident() {
+            return Err(HashError::Argon2Error(
+                argon2::password_hash::Error::Algorithm,
+            ));
+        }
+        Pbkdf2
+            .verify_password(password.as_bytes(), &pwd_hash)
+            .or(Err(HashError::VerificationFail))?;
+        Ok(())
+    }
+}
+
+impl From<LegacyPbkdf2Sha256> for HashScheme {
+    fn from(_value: LegacyPbkdf2Sha256) -> Self {
+        HashScheme::Pbkdf2Sha256
+    }
+}
@@ -0,0 +1,47 @@
+use argon2::{password_hash::PasswordVerifier, PasswordHash};
+use scrypt::Scrypt;
+
+use crate::{
+    error::{HashError, HashResult},
+    hash_scheme::{HashScheme, Hasher},
+};
+
+/// Verify-only scrypt hasher for users imported from other apps.
+/// Expects PHC strings, e.g. `$scrypt$ln=17,r=8,p=1$<salt>$<hash>`.
+pub struct LegacyScrypt;
+
+impl Hasher for LegacyScrypt {
+    fn hash_prefix(&self) -> &'static str {
+        "$scrypt$"
+    }
+    fn hash(
+        &self,
+        _password: &str,
+    ) -> HashResult<(crate::hash_scheme::Hash, crate::hash_scheme::Salt)> {
+        Err(HashError::VerifyOnly)
+    }
+
+    fn hash_with_salt(&self, _password: &str, _salt: &str) -> HashResult<crate::hash_scheme::Hash> {
+        Err(HashError::VerifyOnly)
+    }
+
+    /// The salt is embedded in the PHC string, so `_salt_str` is ignored.
+    fn verify(
+        &self,
+        password: &str,
+        _salt_str: &crate::hash_scheme::Salt,
+        hash_str: &crate::hash_scheme::Hash,
+    ) -> HashResult<()> {
+        let pwd_hash = PasswordHash::new(hash_str)?;
+        Scrypt
+            .verify_password(password.as_bytes(), &pwd_hash)
+            .or(Err(HashError::VerificationFail))?;
+        Ok(())
+    }
+}
+
+impl From<LegacyScrypt> for HashScheme {
+    fn from(_value: LegacyScrypt) -> Self {
+        HashScheme::Scrypt
+    }
+}
@@ -1,2 +1,6 @@
+pub mod argon2_configurable;
 pub mod argon2_v01;
 pub mod argon2_v02;
+pub mod legacy_bcrypt;
+pub mod legacy_pbkdf2;
+pub mod legacy_scrypt;
@@ -1,3 +1,6 @@
 pub mod error;
 pub mod hash_scheme;
 pub mod hashers;
+pub mod pepper;
+pub mod signature;
+pub mod token;
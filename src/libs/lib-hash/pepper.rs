@@ -0,0 +1,85 @@
+use std::{collections::HashMap, fmt::Debug, sync::OnceLock};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::{HashError, HashResult};
+
+pub type PepperId = i16;
+
+static PEPPERS: OnceLock<Peppers> = OnceLock::new();
+
+/// A server-side secret mixed into passwords before hashing. It is never stored in the database.
+/// The id is stored beside each hash so that peppers can be rotated.
+#[derive(Clone)]
+pub struct Pepper {
+    id: PepperId,
+    secret: Vec<u8>,
+}
+
+impl Pepper {
+    pub fn new(id: PepperId, secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            id,
+            secret: secret.into(),
+        }
+    }
+    pub fn id(&self) -> PepperId {
+        self.id
+    }
+    /// Mixes the pepper into a password with HMAC-SHA256, returning the hex digest to hash in place of the password.
+    pub fn apply(&self, password: &str) -> HashResult<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)?;
+        mac.update(password.as_bytes());
+        Ok(format!("{:x}", mac.finalize().into_bytes()))
+    }
+}
+
+impl Debug for Pepper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pepper")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Peppers {
+    current: Option<PepperId>,
+    by_id: HashMap<PepperId, Pepper>,
+}
+
+/// Registers the known peppers and which one new hashes use. Must be called before the first hash is made.
+/// Old peppers must stay registered until no hash references them.
+pub fn configure(peppers: Vec<Pepper>, current: Option<PepperId>) -> HashResult<()> {
+    let by_id = peppers
+        .into_iter()
+        .map(|p| (p.id, p))
+        .collect::<HashMap<_, _>>();
+    if let Some(id) = current {
+        if !by_id.contains_key(&id) {
+            return Err(HashError::UnknownPepper(id));
+        }
+    }
+    PEPPERS
+        .set(Peppers { current, by_id })
+        .or(Err(HashError::AlreadyConfigured))
+}
+
+fn peppers() -> &'static Peppers {
+    PEPPERS.get_or_init(|| Peppers {
+        current: None,
+        by_id: HashMap::new(),
+    })
+}
+
+/// The pepper new hashes are made with, None if peppering is disabled.
+pub fn current() -> Option<&'static Pepper> {
+    let peppers = peppers();
+    peppers.current.and_then(|id| peppers.by_id.get(&id))
+}
+
+/// Looks up the pepper a stored hash was made with.
+pub fn get(id: PepperId) -> HashResult<&'static Pepper> {
+    peppers().by_id.get(&id).ok_or(HashError::UnknownPepper(id))
+}
@@ -0,0 +1,36 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::{HashError, HashResult};
+
+fn mac(secret: &[u8], message: &str) -> HashResult<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)?;
+    mac.update(message.as_bytes());
+    Ok(mac)
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// HMAC-SHA256 hex signature of a message, for links and tokens the server must be able to trust when they come back.
+pub fn sign(secret: &[u8], message: &str) -> HashResult<String> {
+    Ok(format!(
+        "{:x}",
+        mac(secret, message)?.finalize().into_bytes()
+    ))
+}
+
+/// Checks a signature made with `sign` in constant time.
+pub fn verify(secret: &[u8], message: &str, signature: &str) -> HashResult<()> {
+    let signature = from_hex(signature).ok_or(HashError::VerificationFail)?;
+    mac(secret, message)?
+        .verify_slice(&signature)
+        .or(Err(HashError::VerificationFail))
+}
@@ -0,0 +1,21 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+const TOKEN_BYTES: usize = 32;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Generates a random url-safe token. Only its digest should be stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// SHA-256 hex digest of a token, for storing and looking up high entropy tokens.
+/// Passwords should use a `Hasher` instead, since they are too guessable for a fast hash.
+pub fn token_digest(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
@@ -4,7 +4,11 @@ use aws_sdk_s3::{
         delete_object::DeleteObjectError, get_object::GetObjectError, put_object::PutObjectError,
     },
 };
-use axum::{body::Body, http::StatusCode, response::IntoResponse};
+use axum::{
+    body::Body,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::IntoResponse,
+};
 use jwt::error::JWTError;
 use lib_multipart::error::LibMultipartError;
 
@@ -13,12 +17,23 @@ pub type RouterResult<T> = std::result::Result<T, RouteError>;
 #[derive(Debug, Clone)]
 pub enum RouteError {
     Unauthorized,
+    Forbidden,
     MissingAuthCookie,
     MissingJWTSignature,
     LoginFail,
+    /// The account is suspended until the given time, or permanently when None.
+    AccountSuspended {
+        until: Option<chrono::NaiveDateTime>,
+        reason: Option<String>,
+    },
+    /// Too many failed logins, with the number of seconds until the client may retry.
+    LoginLocked(i64),
     InvalidAuth,
     Validation(String),
     AlreadyTaken(String),
+    EmailNotVerified,
+    /// Too many requests, with the number of seconds until the client may retry.
+    RateLimited(i64),
     HashError,
     ExpiredAuthToken,
     ChronoParseError,
@@ -26,6 +41,9 @@ pub enum RouteError {
     IOError(String),
     Sqlx(String),
     AwsSdkError(String),
+    MailerError(String),
+    /// The OpenID Connect provider failed or sent something unexpected.
+    IdentityProvider(String),
     JWTError(JWTError),
     // Used to hide error from users
     Unknown,
@@ -36,6 +54,11 @@ impl IntoResponse for RouteError {
         let mut response = StatusCode::from(&self).into_response();
         let body = Body::new(self.to_string());
         let _ = std::mem::replace(response.body_mut(), body);
+        if let RouteError::RateLimited(secs) | RouteError::LoginLocked(secs) = self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
         response
     }
 }
@@ -47,10 +70,12 @@ impl From<&RouteError> for StatusCode {
             ExpiredAuthToken | MissingJWTSignature | InvalidAuth | MissingAuthCookie
             | LoginFail | Unauthorized | JWTError(_) => StatusCode::UNAUTHORIZED,
             AlreadyTaken(..) => StatusCode::CONFLICT,
+            Forbidden | EmailNotVerified | AccountSuspended { .. } => StatusCode::FORBIDDEN,
+            RateLimited(..) | LoginLocked(..) => StatusCode::TOO_MANY_REQUESTS,
             Validation(..) | LibMultipartError(_) => StatusCode::BAD_REQUEST,
-            AwsSdkError(..) | IOError(..) | HashError | ChronoParseError | Unknown | Sqlx(..) => {
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
+            IdentityProvider(..) => StatusCode::BAD_GATEWAY,
+            AwsSdkError(..) | MailerError(..) | IOError(..) | HashError | ChronoParseError
+            | Unknown | Sqlx(..) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -61,6 +86,12 @@ impl From<lib_models::error::ModelError> for RouteError {
     }
 }
 
+impl From<lib_totp::error::TotpError> for RouteError {
+    fn from(_value: lib_totp::error::TotpError) -> Self {
+        RouteError::Unknown
+    }
+}
+
 impl From<lib_hash::error::HashError> for RouteError {
     fn from(value: lib_hash::error::HashError) -> Self {
         match value {
@@ -129,18 +160,34 @@ impl ToString for RouteError {
         use RouteError::*;
         match self {
             AlreadyTaken(s) => format!("{} already taken", s),
+            EmailNotVerified => format!("Email not verified"),
+            Forbidden => format!("Forbidden"),
+            AccountSuspended { until, reason } => {
+                let mut message = match until {
+                    Some(until) => format!("Account suspended until {} UTC", until),
+                    None => "Account suspended".to_string(),
+                };
+                if let Some(reason) = reason {
+                    message = format!("{}: {}", message, reason);
+                }
+                message
+            }
+            RateLimited(secs) => format!("Too many requests, retry in {} seconds", secs.max(&1)),
             ExpiredAuthToken => format!("Auth token expired"),
             InvalidAuth => format!("Invalid auth token"),
             JWTError(j) => format!("{:?}", j),
             LoginFail => format!("Login failed"),
+            LoginLocked(secs) => {
+                format!("Too many failed logins, retry in {} seconds", secs.max(&1))
+            }
             MissingAuthCookie => format!("Missing auth token"),
             MissingJWTSignature => format!("Missing JWT signature"),
             Validation(s) => s.to_string(),
             LibMultipartError(m) => format!("{:?}", m),
             Unauthorized => "".to_string(),
-            AwsSdkError(..) | Sqlx(..) | IOError(..) | HashError | ChronoParseError | Unknown => {
-                format!("Internal error")
-            }
+            IdentityProvider(..) => format!("Identity provider error"),
+            AwsSdkError(..) | MailerError(..) | Sqlx(..) | IOError(..) | HashError
+            | ChronoParseError | Unknown => format!("Internal error"),
         }
     }
 }
@@ -0,0 +1,13 @@
+pub type TotpResult<T> = Result<T, TotpError>;
+
+#[derive(Debug, Clone)]
+pub enum TotpError {
+    InvalidSecret,
+    InvalidLength,
+}
+
+impl From<hmac::digest::InvalidLength> for TotpError {
+    fn from(_value: hmac::digest::InvalidLength) -> Self {
+        Self::InvalidLength
+    }
+}
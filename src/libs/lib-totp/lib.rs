@@ -0,0 +1,171 @@
+pub mod error;
+
+use base32::Alphabet;
+use error::{TotpError, TotpResult};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+const ALPHABET: Alphabet = Alphabet::Rfc4648 { padding: false };
+
+/// RFC 6238 time-based one time passwords, using the defaults authenticator apps expect
+/// (HMAC-SHA1, 6 digits, 30 second steps).
+pub struct Totp {
+    secret: Vec<u8>,
+}
+
+impl Totp {
+    pub const DIGITS: u32 = 6;
+    pub const STEP_SECONDS: u64 = 30;
+    /// Number of steps either side of now that are still accepted, to allow for clock drift.
+    pub const SKEW_STEPS: u64 = 1;
+    const SECRET_LEN: usize = 20;
+
+    /// Creates a totp with a new random secret.
+    pub fn generate() -> Totp {
+        let mut secret = vec![0u8; Self::SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Totp { secret }
+    }
+    pub fn from_base32(secret: &str) -> TotpResult<Totp> {
+        let secret = base32::decode(ALPHABET, secret).ok_or(TotpError::InvalidSecret)?;
+        if secret.is_empty() {
+            return Err(TotpError::InvalidSecret);
+        }
+        Ok(Totp { secret })
+    }
+    pub fn secret_base32(&self) -> String {
+        base32::encode(ALPHABET, &self.secret)
+    }
+    /// The `otpauth://` uri authenticator apps enroll with, usually shown as a QR code.
+    pub fn provisioning_uri(&self, issuer: &str, account: &str) -> String {
+        let issuer = urlencoding::encode(issuer);
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            issuer,
+            urlencoding::encode(account),
+            self.secret_base32(),
+            issuer,
+            Self::DIGITS,
+            Self::STEP_SECONDS
+        )
+    }
+    /// The time step containing the given unix time.
+    pub fn step_at(unix_secs: u64) -> u64 {
+        unix_secs / Self::STEP_SECONDS
+    }
+    /// The code for a time step (RFC 4226 HOTP with the step as the counter).
+    pub fn code_at_step(&self, step: u64) -> TotpResult<String> {
+        let mut mac = Hmac::<Sha1>::new_from_slice(&self.secret)?;
+        mac.update(&step.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let truncated = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        let code = truncated % 10u32.pow(Self::DIGITS);
+        Ok(format!("{:0width$}", code, width = Self::DIGITS as usize))
+    }
+    /// Checks a code against the steps around the given unix time, returning the step it matched.
+    /// Callers should reject steps at or before the last accepted one so codes cannot be replayed.
+    pub fn verify(&self, code: &str, unix_secs: u64) -> TotpResult<Option<u64>> {
+        let code = code.trim();
+        let now = Self::step_at(unix_secs);
+        for step in now.saturating_sub(Self::SKEW_STEPS)..=now + Self::SKEW_STEPS {
+            if constant_time_eq(self.code_at_step(step)?.as_bytes(), code.as_bytes()) {
+                return Ok(Some(step));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Compares without returning early, so the time taken does not reveal how much of a code was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 secret of the RFC 6238 appendix B test vectors.
+    fn rfc_totp() -> Totp {
+        Totp {
+            secret: b"12345678901234567890".to_vec(),
+        }
+    }
+
+    /// RFC 6238 appendix B SHA-1 vectors, keeping the last 6 of the 8 digits.
+    const VECTORS: &[(u64, &str)] = &[
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    #[test]
+    fn code_at_step_matches_rfc_vectors() {
+        let totp = rfc_totp();
+        for &(unix_secs, code) in VECTORS {
+            assert_eq!(totp.code_at_step(Totp::step_at(unix_secs)).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn verify_accepts_rfc_vectors_at_their_step() {
+        let totp = rfc_totp();
+        for &(unix_secs, code) in VECTORS {
+            assert_eq!(
+                totp.verify(code, unix_secs).unwrap(),
+                Some(Totp::step_at(unix_secs))
+            );
+        }
+    }
+
+    #[test]
+    fn verify_accepts_one_step_either_side() {
+        let totp = rfc_totp();
+        let (unix_secs, code) = VECTORS[2];
+        let step = Totp::step_at(unix_secs);
+        let step_start = step * Totp::STEP_SECONDS;
+
+        assert_eq!(totp.verify(code, step_start - 1).unwrap(), Some(step));
+        assert_eq!(
+            totp.verify(code, step_start + Totp::STEP_SECONDS).unwrap(),
+            Some(step)
+        );
+    }
+
+    #[test]
+    fn verify_rejects_codes_outside_the_window() {
+        let totp = rfc_totp();
+        let (unix_secs, code) = VECTORS[2];
+        let step_start = Totp::step_at(unix_secs) * Totp::STEP_SECONDS;
+
+        assert_eq!(
+            totp.verify(code, step_start - Totp::STEP_SECONDS - 1)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            totp.verify(code, step_start + 2 * Totp::STEP_SECONDS)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn verify_rejects_wrong_codes() {
+        let totp = rfc_totp();
+        let (unix_secs, _) = VECTORS[0];
+        assert_eq!(totp.verify("000000", unix_secs).unwrap(), None);
+        assert_eq!(totp.verify("28708", unix_secs).unwrap(), None);
+    }
+}
@@ -1,15 +1,25 @@
 use aws_sdk_s3::config::Credentials;
 use dotenvy::dotenv;
+use lib_hash::{
+    hashers::argon2_configurable::{self, Argon2Params},
+    pepper::{self, Pepper, PepperId},
+};
+use libs::env::env_or;
 use routes::AppState;
-use services::ndarray::load_models;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::{
-    env,
-    sync::{Arc, Mutex},
-    time::Duration,
+use services::{
+    cold_start::ColdStartConfig,
+    feed_ranking::FeedRankingConfig,
+    mailer::create_mailer,
+    oidc::create_oidc_provider,
+    recommender::spawn_recommender,
+    recommender_snapshot::{load_recommender, SnapshotStore},
+    recommenders::RecommenderConfig,
 };
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
 use tower_http::cors::{Any, CorsLayer};
 
+mod commands;
 mod libs;
 mod middleware;
 mod models;
@@ -18,45 +28,52 @@ mod services;
 
 #[tokio::main]
 async fn main() {
+    let args = env::args().collect::<Vec<_>>();
+    if let Some(command) = args.get(1) {
+        commands::run(command, &args[2..]).await;
+        return;
+    }
+
     let pool = create_pool().await;
+    configure_hashing();
     let s3_client = create_s3_client().await;
 
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .expect("Could not run migrations");
+    run_migrations(&pool).await;
 
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_origin(Any)
         .allow_headers(Any);
 
-    let k_features = 10;
-    let n_observations = 2;
-    let alpha = 0.05;
-    let lambda = 0.1;
-    let epochs = 10;
-
-    let mut ndarray_app_state =
-        load_models(&pool, alpha, lambda, epochs, k_features, n_observations).await;
-    ndarray_app_state.train();
+    let recommender_config = RecommenderConfig::from_env();
+    let snapshot_store = SnapshotStore::from_env(&pool);
+    let model = load_recommender(&pool, recommender_config.clone(), &snapshot_store).await;
+    let recommender = spawn_recommender(pool.clone(), recommender_config, snapshot_store, model);
 
     let app_state = AppState {
         pool,
         s3_client,
-        ndarray_app_state: Arc::new(Mutex::new(ndarray_app_state)),
+        recommender,
+        cold_start: ColdStartConfig::from_env(),
+        feed_ranking: FeedRankingConfig::from_env(),
+        mailer: create_mailer(),
+        oidc: create_oidc_provider().await.map(Arc::new),
     };
     let router = routes::create_routes(app_state).layer(cors);
 
     let addr = "0.0.0.0:3001";
     let listener = tokio::net::TcpListener::bind(addr)
         .await
-        .expect(&format!("Could not listen at {}", addr));
+        .unwrap_or_else(|_| panic!("Could not listen at {}", addr));
 
     println!("Serving on {}", addr);
-    axum::serve(listener, router)
-        .await
-        .expect("Could not serve axum app");
+    // client addresses are needed to throttle logins per ip
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .expect("Could not serve axum app");
 }
 
 async fn create_pool() -> Pool<Postgres> {
@@ -73,6 +90,44 @@ async fn create_pool() -> Pool<Postgres> {
     pool
 }
 
+/// Sets the argon2 cost and pepper of new password hashes.
+/// See the `calibrate-argon2` command for suggested argon2 values.
+fn configure_hashing() {
+    let default = Argon2Params::default();
+    let params = Argon2Params {
+        m_cost: env_or("ARGON2_M_COST", default.m_cost),
+        t_cost: env_or("ARGON2_T_COST", default.t_cost),
+        p_cost: env_or("ARGON2_P_COST", default.p_cost),
+    };
+    argon2_configurable::configure(params).expect("Invalid argon2 params");
+
+    // PASSWORD_PEPPERS="<id>:<secret>,<id>:<secret>", keep retired peppers listed until their hashes are rehashed
+    let peppers = env::var("PASSWORD_PEPPERS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| {
+            let (id, secret) = p
+                .trim()
+                .split_once(':')
+                .expect("PASSWORD_PEPPERS entries must be <id>:<secret>");
+            let id = id.parse::<PepperId>().expect("Invalid pepper id");
+            Pepper::new(id, secret)
+        })
+        .collect::<Vec<_>>();
+    let current = env::var("PASSWORD_PEPPER_ID")
+        .ok()
+        .map(|id| id.parse::<PepperId>().expect("Invalid PASSWORD_PEPPER_ID"));
+    pepper::configure(peppers, current).expect("Invalid password pepper config");
+}
+
+async fn run_migrations(pool: &Pool<Postgres>) {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .expect("Could not run migrations");
+}
+
 async fn create_s3_client() -> aws_sdk_s3::Client {
     let access_key = env::var("ACCESS_KEY_ID").expect("ACCESS_KEY_ID not found in .env");
     let secret_access_key =
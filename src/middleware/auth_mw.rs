@@ -1,40 +1,123 @@
 use std::env;
 
-use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
 use lib_routes::error::{RouteError, RouterResult};
 use once_cell::sync::Lazy;
 use tower_cookies::{Cookie, Cookies};
 
-use ctx::Ctx;
-use jwt::JWT;
+use chrono::TimeDelta;
+use ctx::{AuthUser, Ctx, CtxAuth, Role};
+use jwt::{error::JWTError, JWT, JWT_LIFE_IN_MINUTES};
 
-pub const AUTH_TOKEN: &'static str = "auth_token";
-pub const JWT_SECRET: Lazy<String> = Lazy::new(get_jwt_secret);
+use crate::{
+    models::user_model::get_ctx_user,
+    services::{
+        access_token::{resolve_access_token, ACCESS_TOKEN_PREFIX},
+        suspension::suspension_error,
+    },
+    AppState,
+};
+
+pub const AUTH_TOKEN: &str = "auth_token";
+pub const BEARER_PREFIX: &str = "Bearer ";
+pub static JWT_SECRET: Lazy<String> = Lazy::new(get_jwt_secret);
+/// Signs two-factor login challenges, kept separate from JWT_SECRET so a challenge can never be used as a session.
+pub static TWO_FACTOR_SECRET: Lazy<String> = Lazy::new(get_two_factor_secret);
 
 fn get_jwt_secret() -> String {
     env::var("JWT_SECRET").expect("Could not get JWT_SECRET")
 }
 
-/// Enforces auth Ctx within extensions and validates the jwt
-pub async fn validate_auth(
-    ctx: RouterResult<Ctx>,
-    req: Request<Body>,
-    next: Next,
-) -> RouterResult<Response> {
-    ctx?.jwt().validate_token(&JWT_SECRET)?;
+fn get_two_factor_secret() -> String {
+    env::var("TWO_FACTOR_SECRET").expect("Could not get TWO_FACTOR_SECRET")
+}
+
+/// Enforces auth Ctx within extensions and validates the jwt.
+/// Access tokens were already checked against the database by `ctx_resolver`.
+/// Also rejects tokens whose account was deleted, see `AuthUser`. Suspended accounts were rejected by `ctx_resolver`.
+/// Reads the Ctx from the extensions rather than the extractor, which rejects access tokens
+/// until the scope layers of the route have run.
+pub async fn validate_auth(req: Request<Body>, next: Next) -> RouterResult<Response> {
+    let ctx = Ctx::from_extensions(req.extensions())?;
+    if let CtxAuth::Session(jwt) = ctx.auth() {
+        jwt.validate_token(&JWT_SECRET)?;
+    }
+    AuthUser::try_from(ctx)?;
     Ok(next.run(req).await)
 }
 
-/// Creates Ctx from cookies and inserts into Extensions then calls next layer.
-/// Returns Err if missing or invalid JWT.
+/// Adds the id and roles of the account behind the token.
+/// The id is left unset for deleted accounts, which `AuthUser` and `validate_auth` reject.
+/// Errs with AccountSuspended while the account's suspension is in effect,
+/// and with ExpiredJWT for sessions issued before the password was last changed or reset.
+/// Roles are only resolved for sessions, access tokens are limited to their scopes.
+async fn resolve_user(s: &AppState, ctx: Ctx) -> RouterResult<Ctx> {
+    let Some(user) = get_ctx_user(ctx.username(), &s.pool).await? else {
+        return Ok(ctx);
+    };
+    if let Some(e) = suspension_error(&user) {
+        return Err(e);
+    }
+    if let (CtxAuth::Session(jwt), Some(valid_after)) = (ctx.auth(), user.sessions_valid_after) {
+        // sessions always live for JWT_LIFE_IN_MINUTES, see `set_session_cookie`
+        let issued_at = *jwt.expires() - TimeDelta::minutes(JWT_LIFE_IN_MINUTES);
+        if issued_at.naive_utc() < valid_after {
+            return Err(RouteError::JWTError(JWTError::ExpiredJWT));
+        }
+    }
+    let roles = match ctx.auth() {
+        CtxAuth::Session(_) => user
+            .roles
+            .iter()
+            .filter_map(|r| r.parse::<Role>().ok())
+            .collect(),
+        CtxAuth::AccessToken { .. } => Vec::new(),
+    };
+    Ok(ctx.with_user_id(Some(user.id)).with_roles(roles))
+}
+
+/// Returns the token from an `Authorization: Bearer <token>` header, if present.
+/// The scheme is case-insensitive.
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let scheme = value.get(..BEARER_PREFIX.len())?;
+    if !scheme.eq_ignore_ascii_case(BEARER_PREFIX) {
+        return None;
+    }
+    let token = value[BEARER_PREFIX.len()..].trim();
+    if token.is_empty() {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+/// Creates Ctx from the Authorization header or cookies and inserts into Extensions then calls next layer.
+/// The bearer token takes precedence over the auth cookie, and may be a personal access token.
+/// The user's id and roles are looked up once here for the rest of the request.
+/// Inserts Err if missing or invalid JWT, or if the token could not be looked up,
+/// so that routes which do not need auth are unaffected.
 pub async fn ctx_resolver(
+    State(s): State<AppState>,
     cookies: Cookies,
     mut req: Request<Body>,
     next: Next,
 ) -> RouterResult<Response> {
-    let token_str = cookies.get(AUTH_TOKEN).map(|c| c.value().to_string());
+    let header_token = bearer_token(req.headers());
+    let from_cookie = header_token.is_none();
+    let token_str = header_token.or_else(|| cookies.get(AUTH_TOKEN).map(|c| c.value().to_string()));
 
     let result_ctx: Result<Ctx, RouteError> = match token_str {
+        Some(t) if !from_cookie && t.starts_with(ACCESS_TOKEN_PREFIX) => {
+            resolve_access_token(&s.pool, &t)
+                .await
+                .and_then(|ctx| ctx.ok_or(RouteError::InvalidAuth))
+        }
         Some(t) => match JWT::parse_token(t) {
             Ok(jwt) => Ok(Ctx::new(jwt)),
             Err(e) => Err(RouteError::JWTError(e)),
@@ -42,10 +125,73 @@ pub async fn ctx_resolver(
         None => Err(RouteError::MissingAuthCookie),
     };
 
-    if result_ctx.is_err() && !matches!(result_ctx, Err(RouteError::MissingAuthCookie)) {
+    let result_ctx = match result_ctx {
+        Ok(ctx) => resolve_user(&s, ctx).await,
+        r => r,
+    };
+
+    if from_cookie
+        && result_ctx.is_err()
+        && !matches!(result_ctx, Err(RouteError::MissingAuthCookie))
+    {
         cookies.remove(Cookie::from(AUTH_TOKEN));
     }
 
     req.extensions_mut().insert(result_ctx);
     Ok(next.run(req).await)
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::StatusCode,
+        middleware::{from_fn, Next},
+        routing::get,
+        Router,
+    };
+    use ctx::{FeedReadScope, Scope};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::middleware::scope_mw::require_scope;
+
+    /// Stands in for `ctx_resolver`, which looks the access token up in the database.
+    async fn resolve_test_token(mut req: Request<Body>, next: Next) -> Response {
+        let token = bearer_token(req.headers()).expect("missing bearer token");
+        assert!(token.starts_with(ACCESS_TOKEN_PREFIX));
+        let ctx = Ctx::from_access_token("user".to_string(), 1, vec![Scope::FeedRead])
+            .with_user_id(Some(1));
+        req.extensions_mut().insert::<RouterResult<Ctx>>(Ok(ctx));
+        next.run(req).await
+    }
+
+    /// Layered like `create_routes`, with `validate_auth` outside the scope of the route.
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/scoped",
+                get(|_: Ctx| async {}).route_layer(from_fn(require_scope::<FeedReadScope>)),
+            )
+            .route("/unscoped", get(|_: Ctx| async {}))
+            .layer(from_fn(validate_auth))
+            .layer(from_fn(resolve_test_token))
+    }
+
+    async fn status(uri: &str) -> StatusCode {
+        let req = Request::get(uri)
+            .header(AUTHORIZATION, format!("bearer {}test", ACCESS_TOKEN_PREFIX))
+            .body(Body::empty())
+            .unwrap();
+        app().oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn access_token_reaches_scoped_route() {
+        assert_eq!(status("/scoped").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn access_token_rejected_on_unscoped_route() {
+        assert_eq!(status("/unscoped").await, StatusCode::FORBIDDEN);
+    }
+}
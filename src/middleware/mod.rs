@@ -1,2 +1,4 @@
 pub mod auth_mw;
 pub mod logger_mw;
+pub mod role_mw;
+pub mod scope_mw;
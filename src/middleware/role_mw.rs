@@ -0,0 +1,14 @@
+use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use ctx::{Ctx, RequiredRole};
+use lib_routes::error::RouterResult;
+
+/// Requires the role `R` for every route in a router, e.g.
+/// `.route_layer(from_fn(require_role::<ModeratorRole>))` in `NestedRoute::router`.
+/// See `ctx::HasRole` to require a role on a single handler.
+pub async fn require_role<R: RequiredRole>(
+    req: Request<Body>,
+    next: Next,
+) -> RouterResult<Response> {
+    Ctx::from_extensions(req.extensions())?.require_role(R::ROLE)?;
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,19 @@
+use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use ctx::{Ctx, RequiredScope};
+use lib_routes::error::{RouteError, RouterResult};
+
+/// Declares the scope an access token needs for a route, e.g.
+/// `.route("/", get(handler).route_layer(from_fn(require_scope::<FeedReadScope>)))`.
+/// Access tokens are rejected by the Ctx extractor on routes without one, sessions are always allowed.
+pub async fn require_scope<S: RequiredScope>(
+    mut req: Request<Body>,
+    next: Next,
+) -> RouterResult<Response> {
+    let ctx = req
+        .extensions_mut()
+        .remove::<RouterResult<Ctx>>()
+        .ok_or(RouteError::InvalidAuth)??;
+    let ctx = ctx.grant_scope(S::SCOPE)?;
+    req.extensions_mut().insert::<RouterResult<Ctx>>(Ok(ctx));
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,123 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct AccessTokenModel {
+    pub id: i64,
+    pub username: String,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl DbBmc for AccessTokenModel {
+    const TABLE: &'static str = "user_management.access_tokens";
+}
+
+/// An access token as shown to its owner, without its digest.
+#[derive(Serialize, FromRow, Debug)]
+pub struct ReadAccessTokenModel {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+pub struct CreateAccessTokenModel {
+    pub username: String,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i32>,
+}
+
+pub async fn create_access_token(
+    pool: &PgPool,
+    data: CreateAccessTokenModel,
+) -> ModelResult<ReadAccessTokenModel> {
+    let token = sqlx::query_as::<_, ReadAccessTokenModel>(&format!(
+        "INSERT INTO {} (username, name, token_hash, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, now() + make_interval(days => $5))
+        RETURNING id, name, scopes, created_at, expires_at, last_used_at;",
+        AccessTokenModel::TABLE
+    ))
+    .bind(data.username)
+    .bind(data.name)
+    .bind(data.token_hash)
+    .bind(data.scopes)
+    .bind(data.expires_in_days)
+    .fetch_one(pool)
+    .await?;
+    Ok(token)
+}
+
+/// Lists a user's tokens that have not been revoked, including expired ones.
+pub async fn list_access_tokens(
+    pool: &PgPool,
+    username: &str,
+) -> ModelResult<Vec<ReadAccessTokenModel>> {
+    let tokens = sqlx::query_as::<_, ReadAccessTokenModel>(&format!(
+        "SELECT id, name, scopes, created_at, expires_at, last_used_at FROM {}
+        WHERE username = $1 AND revoked_at IS NULL ORDER BY created_at DESC;",
+        AccessTokenModel::TABLE
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await?;
+    Ok(tokens)
+}
+
+/// Gets an unrevoked, unexpired token by its digest, recording that it was used.
+pub async fn use_access_token(
+    pool: &PgPool,
+    token_hash: &str,
+) -> ModelResult<Option<AccessTokenModel>> {
+    let token = sqlx::query_as::<_, AccessTokenModel>(&format!(
+        "UPDATE {} SET last_used_at = now()
+        WHERE token_hash = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now())
+        RETURNING *;",
+        AccessTokenModel::TABLE
+    ))
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await?;
+    Ok(token)
+}
+
+/// Revokes one of a user's tokens, returning the number of rows affected.
+pub async fn revoke_access_token(pool: &PgPool, username: &str, id: i64) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET revoked_at = now() WHERE id = $1 AND username = $2 AND revoked_at IS NULL;",
+        AccessTokenModel::TABLE
+    ))
+    .bind(id)
+    .bind(username)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
+
+/// Revokes all of a user's tokens, e.g. when their password changes, returning the number of rows affected.
+pub async fn revoke_all_access_tokens(pool: &PgPool, username: &str) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET revoked_at = now() WHERE username = $1 AND revoked_at IS NULL;",
+        AccessTokenModel::TABLE
+    ))
+    .bind(username)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
@@ -1,15 +1,19 @@
+use std::collections::HashMap;
+
 use chrono::NaiveDateTime;
-use itertools::Itertools;
 use lib_models::error::ModelResult;
 use serde::{Deserialize, Serialize};
 use sqlb::{Fields, SqlxBindable};
 use sqlx::{prelude::FromRow, PgPool};
 
-use crate::routes::AppState;
+use crate::{routes::AppState, services::cold_start::ColdStartSignals};
 
 use super::base::DbBmc;
+use super::following_model::FollowingModel;
+use super::likes_model::LikesModel;
+use super::user_model::{active_suspension_sql, UserModel};
 
-#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone)]
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[sqlx(type_name = "post_type")]
 pub enum PostType {
     #[sqlx(rename = "images")]
@@ -56,26 +60,22 @@ pub async fn get_three_older(
     pool: &PgPool,
     created_at: &NaiveDateTime,
 ) -> ModelResult<Vec<ContentModel>> {
-    let row = sqlb::select()
-        .table(ContentModel::TABLE)
-        .columns(&[
-            "id",
-            "username",
-            "num_images",
-            "description",
-            "post_type",
-            "created_at",
-            "deactivated_at",
-        ])
-        .and_where("created_at", "<", NaiveDateTimeWrapper(created_at))
-        .order_by("!created_at")
-        .limit(3)
-        .fetch_all::<_, ContentModel>(pool)
-        .await?;
+    let row = sqlx::query_as::<_, ContentModel>(&format!(
+        "SELECT id, username, num_images, description, post_type, created_at, deactivated_at
+        FROM {} p WHERE p.created_at < $1 AND p.deactivated_at IS NULL
+        AND NOT EXISTS (SELECT 1 FROM {} u WHERE u.username = p.username AND {})
+        ORDER BY p.created_at DESC LIMIT 3;",
+        ContentModel::TABLE,
+        UserModel::TABLE,
+        active_suspension_sql("u")
+    ))
+    .bind(created_at)
+    .fetch_all(pool)
+    .await?;
     Ok(row)
 }
 
-pub async fn get_ten_unseen_older<'q>(
+pub async fn get_ten_unseen_older(
     pool: &PgPool,
     created_at: &NaiveDateTime,
     username: &str,
@@ -100,9 +100,20 @@ pub async fn get_ten_unseen_older<'q>(
             )
             AND
             p.created_at < $2
+            AND
+            p.deactivated_at IS NULL
+            AND
+            NOT EXISTS (
+                SELECT 1
+                FROM {} u
+                WHERE u.username = p.username
+                AND {}
+            )
         ORDER BY p.created_at DESC
         LIMIT 10;
         ",
+        UserModel::TABLE,
+        active_suspension_sql("u")
     ))
     .bind(username)
     .bind(created_at)
@@ -111,40 +122,108 @@ pub async fn get_ten_unseen_older<'q>(
     Ok(rows)
 }
 
-#[derive(Debug)]
-pub struct NaiveDateTimeWrapper<'a>(&'a NaiveDateTime);
-
-impl SqlxBindable for NaiveDateTimeWrapper<'_> {
-    fn bind_query<'q>(
-        &'q self,
-        query: sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments>,
-    ) -> sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments> {
-        query.bind(self.0)
-    }
-}
-
-pub fn sort_by_predicted(
+/// Sorts by the recommender's scores, posts it cannot score for the user get the cold start score instead.
+/// The top `num_taken` are then re-ranked for diversity and exploration, see `FeedRankingConfig`.
+pub async fn sort_by_predicted(
     posts: &mut Vec<ContentModel>,
     s: &AppState,
     num_taken: usize,
     user_id: i64,
-) {
+    username: &str,
+) -> ModelResult<()> {
     let post_ids = posts.iter().map(|p| p.id).collect::<Vec<_>>();
-    let predictions = s
-        .ndarray_app_state
-        .lock()
-        .expect("err locking")
-        .predict_all(user_id, &post_ids);
-
-    let zipped = posts.iter().zip(predictions.iter()).collect::<Vec<_>>();
-    *posts = zipped
+    let predicted = s.recommender.model().score(user_id, &post_ids);
+
+    let unknown = post_ids
         .iter()
-        .sorted_by(|a, b| {
-            b.1 .1
-                .partial_cmp(&a.1 .1)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        })
-        .map(|&(x, _)| x.clone())
-        .take(num_taken)
+        .zip(&predicted)
+        .filter(|(_, score)| score.is_none())
+        .map(|(&post_id, _)| post_id)
         .collect::<Vec<_>>();
+    let signals = if unknown.is_empty() {
+        HashMap::new()
+    } else {
+        get_cold_start_signals(&s.pool, username, &unknown).await?
+    };
+
+    let scores = posts.iter().zip(predicted).map(|(post, score)| {
+        score.unwrap_or_else(|| {
+            let signals = signals.get(&post.id).copied().unwrap_or_default();
+            s.cold_start.score(&post.post_type, signals)
+        })
+    });
+
+    let scored = posts.iter().cloned().zip(scores).collect::<Vec<_>>();
+    *posts = s.feed_ranking.rerank(scored, num_taken);
+    Ok(())
+}
+
+/// The number of likes of each post and whether `username` follows its author, by post id.
+pub async fn get_cold_start_signals(
+    pool: &PgPool,
+    username: &str,
+    post_ids: &[i64],
+) -> ModelResult<HashMap<i64, ColdStartSignals>> {
+    let rows = sqlx::query_as::<_, (i64, i64, bool)>(&format!(
+        "
+        SELECT
+            p.id,
+            (SELECT COUNT(*) FROM {} l WHERE l.post_id = p.id),
+            EXISTS (
+                SELECT 1
+                FROM {} f
+                WHERE f.follower = $1
+                AND f.following = p.username
+            )
+        FROM {} p
+        WHERE p.id = ANY($2);
+        ",
+        LikesModel::TABLE,
+        FollowingModel::TABLE,
+        ContentModel::TABLE
+    ))
+    .bind(username)
+    .bind(post_ids)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(post_id, num_likes, is_following)| {
+            let signals = ColdStartSignals {
+                num_likes,
+                is_following,
+            };
+            (post_id, signals)
+        })
+        .collect())
+}
+
+pub async fn get_post_ids_by_username(pool: &PgPool, username: &str) -> ModelResult<Vec<i64>> {
+    let ids = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT id FROM {} WHERE username = $1;",
+        ContentModel::TABLE
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await?;
+    Ok(ids)
+}
+
+/// Hides or restores a post, returning the number of rows affected.
+/// Hidden posts are left in place so that a moderator can restore them.
+pub async fn set_post_deactivated(
+    pool: &PgPool,
+    post_id: i64,
+    deactivated: bool,
+) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET deactivated_at = CASE WHEN $2 THEN now() ELSE NULL END WHERE id = $1;",
+        ContentModel::TABLE
+    ))
+    .bind(post_id)
+    .bind(deactivated)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
 }
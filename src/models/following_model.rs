@@ -29,5 +29,5 @@ pub async fn is_following(
         pool,
     )
     .await?;
-    return Ok(res.is_some());
+    Ok(res.is_some())
 }
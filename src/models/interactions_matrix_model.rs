@@ -1,47 +1,82 @@
+use chrono::NaiveDateTime;
 use lib_models::error::ModelResult;
 use serde::{Deserialize, Serialize};
 use sqlx::{prelude::FromRow, PgPool};
 
-#[derive(Deserialize, Serialize, FromRow, Debug)]
+/// A user and post pair with at least one interaction, pairs without any are not returned.
+#[derive(Deserialize, Serialize, FromRow, Debug, Clone)]
 pub struct InteractionsMatrixModel {
     pub user_id: i64,
     pub post_id: i64,
     pub is_liked: i32,
+    /// Whether the user follows the author of the post.
     pub is_following: i32,
-    pub username: String,
+    pub is_seen: i32,
+    pub comments: i64,
+    /// Visits of the user to the profile of the author of the post.
+    pub profile_visits: i64,
+    pub dwell_ms: i64,
 }
 
 pub async fn build_model(pool: &PgPool) -> ModelResult<Vec<InteractionsMatrixModel>> {
     let join_query = sqlx::query_as::<_, InteractionsMatrixModel>(
         "
         SELECT
-            u.username,
             u.id as user_id,
-            p.id as post_id,
-            COALESCE(like_data.is_liked, 0) as is_liked,
-            COALESCE(follow_data.is_following, 0) as is_following
-        FROM user_management.users u
-        CROSS JOIN post_management.posts p
-        LEFT JOIN
+            pairs.post_id,
+            MAX(pairs.is_liked)::int as is_liked,
+            MAX(pairs.is_following)::int as is_following,
+            MAX(pairs.is_seen)::int as is_seen,
+            SUM(pairs.comments)::bigint as comments,
+            MAX(pairs.profile_visits)::bigint as profile_visits,
+            MAX(pairs.dwell_ms)::bigint as dwell_ms
+        FROM
             (
                 SELECT
                     username,
                     post_id,
-                    1 as is_liked
+                    1 as is_liked,
+                    0 as is_following,
+                    0 as is_seen,
+                    0 as comments,
+                    0 as profile_visits,
+                    0::bigint as dwell_ms
                 FROM
                     post_management.likes
-            ) like_data
-            ON u.username = like_data.username AND p.id = like_data.post_id
-        LEFT JOIN
-            (
+                UNION ALL
+                SELECT
+                    f.follower as username,
+                    p.id as post_id,
+                    0, 1, 0, 0, 0, 0
+                FROM
+                    user_management.following f
+                JOIN post_management.posts p ON p.username = f.following
+                UNION ALL
+                SELECT
+                    username,
+                    post_id,
+                    0, 0, 1, 0, 0, dwell_ms
+                FROM
+                    post_management.seen_posts
+                UNION ALL
+                SELECT
+                    username,
+                    post_id,
+                    0, 0, 0, 1, 0, 0
+                FROM
+                    post_management.comments
+                WHERE deactivated_at IS NULL
+                UNION ALL
                 SELECT
-                    follower,
-                    following,
-                    1 as is_following
+                    v.visitor as username,
+                    p.id as post_id,
+                    0, 0, 0, 0, v.visits, 0
                 FROM
-                    user_management.following
-            ) follow_data
-            ON u.username = follow_data.follower AND p.username = follow_data.following
+                    user_management.profile_visits v
+                JOIN post_management.posts p ON p.username = v.visited
+            ) pairs
+        JOIN user_management.users u ON u.username = pairs.username
+        GROUP BY u.id, pairs.post_id
         ;
             ",
     )
@@ -50,3 +85,49 @@ pub async fn build_model(pool: &PgPool) -> ModelResult<Vec<InteractionsMatrixMod
 
     Ok(join_query)
 }
+
+pub async fn get_user_ids(pool: &PgPool) -> ModelResult<Vec<i64>> {
+    let ids = sqlx::query_scalar::<_, i64>("SELECT id FROM user_management.users ORDER BY id;")
+        .fetch_all(pool)
+        .await?;
+    Ok(ids)
+}
+
+#[derive(Deserialize, Serialize, FromRow, Debug, Clone)]
+pub struct PostTimeModel {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn get_posts(pool: &PgPool) -> ModelResult<Vec<PostTimeModel>> {
+    let posts = sqlx::query_as::<_, PostTimeModel>(
+        "SELECT id, created_at FROM post_management.posts ORDER BY id;",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(posts)
+}
+
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct LikeEventModel {
+    pub user_id: i64,
+    pub post_id: i64,
+}
+
+/// Every like, oldest first.
+pub async fn get_likes_by_time(pool: &PgPool) -> ModelResult<Vec<LikeEventModel>> {
+    let likes = sqlx::query_as::<_, LikeEventModel>(
+        "
+        SELECT
+            u.id as user_id,
+            l.post_id
+        FROM post_management.likes l
+        JOIN user_management.users u ON u.username = l.username
+        ORDER BY l.created_at, l.id
+        ;
+            ",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(likes)
+}
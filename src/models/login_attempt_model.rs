@@ -0,0 +1,73 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct LoginAttemptModel {
+    pub key: String,
+    pub failures: i32,
+    pub last_failure_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+impl DbBmc for LoginAttemptModel {
+    const TABLE: &'static str = "user_management.login_attempts";
+}
+
+/// Seconds until the longest running lockout among `keys` ends, 0 if none are locked.
+pub async fn locked_for(pool: &PgPool, keys: &[String]) -> ModelResult<i64> {
+    let secs = sqlx::query_scalar::<_, Option<i64>>(&format!(
+        "SELECT CEIL(EXTRACT(EPOCH FROM MAX(locked_until) - now()))::bigint
+        FROM {} WHERE key = ANY($1) AND locked_until > now();",
+        LoginAttemptModel::TABLE
+    ))
+    .bind(keys)
+    .fetch_one(pool)
+    .await?;
+    Ok(secs.unwrap_or(0).max(0))
+}
+
+/// Counts a failed attempt, starting the count over if the last failure was more than `window_secs` ago.
+/// Returns the number of failures in the current window.
+pub async fn record_failure(pool: &PgPool, key: &str, window_secs: i64) -> ModelResult<i32> {
+    let failures = sqlx::query_scalar::<_, i32>(&format!(
+        "INSERT INTO {0} AS a (key, failures, last_failure_at) VALUES ($1, 1, now())
+        ON CONFLICT (key) DO UPDATE SET
+            failures = CASE WHEN a.last_failure_at < now() - make_interval(secs => $2) THEN 1 ELSE a.failures + 1 END,
+            last_failure_at = now()
+        RETURNING failures;",
+        LoginAttemptModel::TABLE
+    ))
+    .bind(key)
+    .bind(window_secs as f64)
+    .fetch_one(pool)
+    .await?;
+    Ok(failures)
+}
+
+pub async fn lock(pool: &PgPool, key: &str, secs: i64) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "UPDATE {} SET locked_until = now() + make_interval(secs => $2) WHERE key = $1;",
+        LoginAttemptModel::TABLE
+    ))
+    .bind(key)
+    .bind(secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn clear(pool: &PgPool, key: &str) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE key = $1;",
+        LoginAttemptModel::TABLE
+    ))
+    .bind(key)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
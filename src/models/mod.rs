@@ -1,9 +1,18 @@
+pub mod access_token_model;
 pub mod base;
 pub mod content_model;
 pub mod exercise_preset_model;
 pub mod following_model;
 pub mod interactions_matrix_model;
 pub mod likes_model;
+pub mod login_attempt_model;
+pub mod oidc_model;
+pub mod password_reset_model;
 pub mod profile_picture_model;
+pub mod profile_visit_model;
+pub mod recommender_snapshot_model;
+pub mod recovery_code_model;
+pub mod role_model;
 pub mod seen_posts_model;
+pub mod totp_model;
 pub mod user_model;
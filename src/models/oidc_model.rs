@@ -0,0 +1,116 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct OidcLoginStateModel {
+    pub state: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+impl DbBmc for OidcLoginStateModel {
+    const TABLE: &'static str = "user_management.oidc_login_states";
+}
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct OidcIdentityModel {
+    pub id: i64,
+    pub issuer: String,
+    pub subject: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: NaiveDateTime,
+}
+
+impl DbBmc for OidcIdentityModel {
+    const TABLE: &'static str = "user_management.oidc_identities";
+}
+
+pub async fn create_login_state(
+    pool: &PgPool,
+    state: &str,
+    code_verifier: &str,
+    nonce: &str,
+    life_in_minutes: i32,
+) -> ModelResult<()> {
+    // expired states are never consumed, so clear them out as new ones are made
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE expires_at <= now();",
+        OidcLoginStateModel::TABLE
+    ))
+    .execute(pool)
+    .await?;
+    sqlx::query(&format!(
+        "INSERT INTO {} (state, code_verifier, nonce, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(mins => $4));",
+        OidcLoginStateModel::TABLE
+    ))
+    .bind(state)
+    .bind(code_verifier)
+    .bind(nonce)
+    .bind(life_in_minutes)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Deletes and returns an unexpired login state, so each state can only be used once.
+pub async fn consume_login_state(
+    pool: &PgPool,
+    state: &str,
+) -> ModelResult<Option<OidcLoginStateModel>> {
+    let login_state = sqlx::query_as::<_, OidcLoginStateModel>(&format!(
+        "DELETE FROM {} WHERE state = $1 AND expires_at > now() RETURNING *;",
+        OidcLoginStateModel::TABLE
+    ))
+    .bind(state)
+    .fetch_optional(pool)
+    .await?;
+    Ok(login_state)
+}
+
+/// Gets the username linked to a provider identity, recording the login.
+pub async fn use_identity(
+    pool: &PgPool,
+    issuer: &str,
+    subject: &str,
+) -> ModelResult<Option<String>> {
+    let username = sqlx::query_scalar::<_, String>(&format!(
+        "UPDATE {} SET last_login_at = now() WHERE issuer = $1 AND subject = $2 RETURNING username;",
+        OidcIdentityModel::TABLE
+    ))
+    .bind(issuer)
+    .bind(subject)
+    .fetch_optional(pool)
+    .await?;
+    Ok(username)
+}
+
+pub async fn link_identity(
+    pool: &PgPool,
+    issuer: &str,
+    subject: &str,
+    username: &str,
+    email: Option<&str>,
+) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "INSERT INTO {} (issuer, subject, username, email) VALUES ($1, $2, $3, $4);",
+        OidcIdentityModel::TABLE
+    ))
+    .bind(issuer)
+    .bind(subject)
+    .bind(username)
+    .bind(email)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
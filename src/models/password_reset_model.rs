@@ -0,0 +1,114 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct PasswordResetModel {
+    pub id: i64,
+    pub username: String,
+    pub token_hash: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl DbBmc for PasswordResetModel {
+    const TABLE: &'static str = "user_management.password_reset_tokens";
+}
+
+pub async fn create_reset_token(
+    pool: &PgPool,
+    username: &str,
+    token_hash: &str,
+    life_in_minutes: i32,
+) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "INSERT INTO {} (username, token_hash, expires_at)
+        VALUES ($1, $2, now() + make_interval(mins => $3));",
+        PasswordResetModel::TABLE
+    ))
+    .bind(username)
+    .bind(token_hash)
+    .bind(life_in_minutes)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Marks an unused, unexpired reset token used, returning its username.
+/// None if there is no such token, including when a concurrent request consumed it first.
+pub async fn consume_reset_token(pool: &PgPool, token_hash: &str) -> ModelResult<Option<String>> {
+    let username = sqlx::query_scalar::<_, String>(&format!(
+        "UPDATE {} SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING username;",
+        PasswordResetModel::TABLE
+    ))
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await?;
+    Ok(username)
+}
+
+/// Marks all of a user's outstanding reset tokens as used.
+pub async fn use_reset_tokens(pool: &PgPool, username: &str) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET used_at = now() WHERE username = $1 AND used_at IS NULL;",
+        PasswordResetModel::TABLE
+    ))
+    .bind(username)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct PasswordResetRequestModel {
+    pub email: String,
+    pub requested_at: NaiveDateTime,
+}
+
+impl DbBmc for PasswordResetRequestModel {
+    const TABLE: &'static str = "user_management.password_reset_requests";
+}
+
+/// Records a reset request for the email unless one was made within `interval_secs`.
+/// Returns false if the request is rate limited.
+pub async fn try_set_reset_requested(
+    pool: &PgPool,
+    email: &str,
+    interval_secs: i64,
+) -> ModelResult<bool> {
+    let rows_affected = sqlx::query(&format!(
+        "INSERT INTO {} AS r (email) VALUES ($1)
+        ON CONFLICT (email) DO UPDATE SET requested_at = now()
+        WHERE r.requested_at <= now() - make_interval(secs => $2);",
+        PasswordResetRequestModel::TABLE
+    ))
+    .bind(email)
+    .bind(interval_secs as f64)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+/// Seconds until another reset may be requested for the email, 0 if one may be requested now.
+pub async fn reset_retry_after(pool: &PgPool, email: &str, interval_secs: i64) -> ModelResult<i64> {
+    let secs = sqlx::query_scalar::<_, Option<i64>>(&format!(
+        "SELECT CEIL(EXTRACT(EPOCH FROM requested_at + make_interval(secs => $2) - now()))::bigint
+        FROM {} WHERE email = $1",
+        PasswordResetRequestModel::TABLE
+    ))
+    .bind(email)
+    .bind(interval_secs as f64)
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+    Ok(secs.unwrap_or(0).max(0))
+}
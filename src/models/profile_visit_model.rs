@@ -0,0 +1,33 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct ProfileVisitModel {
+    pub visitor: String,
+    pub visited: String,
+    pub visits: i32,
+    pub last_visited_at: NaiveDateTime,
+}
+
+impl DbBmc for ProfileVisitModel {
+    const TABLE: &'static str = "user_management.profile_visits";
+}
+
+/// Counts a visit of `visitor` to the profile of `visited`.
+pub async fn record_profile_visit(pool: &PgPool, visitor: &str, visited: &str) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "INSERT INTO {} AS v (visitor, visited) VALUES ($1, $2)
+        ON CONFLICT (visitor, visited) DO UPDATE SET visits = v.visits + 1, last_visited_at = now();",
+        ProfileVisitModel::TABLE
+    ))
+    .bind(visitor)
+    .bind(visited)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[allow(unused)]
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct RecommenderSnapshotModel {
+    pub id: i64,
+    pub version: i32,
+    pub created_at: NaiveDateTime,
+    pub data: Vec<u8>,
+}
+
+impl DbBmc for RecommenderSnapshotModel {
+    const TABLE: &'static str = "recommender.snapshots";
+}
+
+/// Saves a snapshot, keeping only the newest `keep` snapshots.
+pub async fn save_snapshot(pool: &PgPool, version: i32, data: &[u8], keep: i64) -> ModelResult<()> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query(&format!(
+        "INSERT INTO {} (version, data) VALUES ($1, $2);",
+        RecommenderSnapshotModel::TABLE
+    ))
+    .bind(version)
+    .bind(data)
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query(&format!(
+        "DELETE FROM {0} WHERE id NOT IN (SELECT id FROM {0} ORDER BY id DESC LIMIT $1);",
+        RecommenderSnapshotModel::TABLE
+    ))
+    .bind(keep)
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// The data of the newest snapshot with the given format version.
+pub async fn get_latest_snapshot(pool: &PgPool, version: i32) -> ModelResult<Option<Vec<u8>>> {
+    let data = sqlx::query_scalar::<_, Vec<u8>>(&format!(
+        "SELECT data FROM {} WHERE version = $1 ORDER BY id DESC LIMIT 1;",
+        RecommenderSnapshotModel::TABLE
+    ))
+    .bind(version)
+    .fetch_optional(pool)
+    .await?;
+    Ok(data)
+}
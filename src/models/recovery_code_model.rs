@@ -0,0 +1,81 @@
+use chrono::NaiveDateTime;
+use lib_hash::hash_scheme::HashScheme;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlb::Fields;
+use sqlx::{prelude::FromRow, PgPool, Postgres, Transaction};
+
+use super::base::{self, DbBmc};
+
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct RecoveryCodeModel {
+    pub id: i64,
+    pub username: String,
+    pub code_hash: String,
+    pub code_salt: String,
+    pub hash_scheme: HashScheme,
+    pub pepper_id: Option<i16>,
+    pub created_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl DbBmc for RecoveryCodeModel {
+    const TABLE: &'static str = "user_management.recovery_codes";
+}
+
+#[derive(Fields)]
+pub struct CreateRecoveryCodeModel {
+    pub username: String,
+    pub code_hash: String,
+    pub code_salt: String,
+    pub hash_scheme: HashScheme,
+    pub pepper_id: Option<i16>,
+}
+
+/// Replaces all of a user's recovery codes with the given ones.
+pub async fn replace_recovery_codes(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    codes: Vec<CreateRecoveryCodeModel>,
+) -> ModelResult<()> {
+    sqlb::delete()
+        .table(RecoveryCodeModel::TABLE)
+        .and_where_eq("username", username)
+        .exec(&mut **transaction)
+        .await?;
+    for code in codes {
+        base::create_with_transaction::<RecoveryCodeModel, _>(code, transaction).await?;
+    }
+    Ok(())
+}
+
+pub async fn get_unused_recovery_codes(
+    pool: &PgPool,
+    username: &str,
+) -> ModelResult<Vec<RecoveryCodeModel>> {
+    let codes = sqlx::query_as::<_, RecoveryCodeModel>(&format!(
+        "SELECT * FROM {} WHERE username = $1 AND used_at IS NULL;",
+        RecoveryCodeModel::TABLE
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await?;
+    Ok(codes)
+}
+
+/// Marks a recovery code as used, returning false if it was already used.
+pub async fn use_recovery_code(pool: &PgPool, id: i64) -> ModelResult<bool> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET used_at = now() WHERE id = $1 AND used_at IS NULL;",
+        RecoveryCodeModel::TABLE
+    ))
+    .bind(id)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+pub async fn delete_recovery_codes(pool: &PgPool, username: &str) -> ModelResult<u64> {
+    base::delete::<RecoveryCodeModel, _>("username", username, pool).await
+}
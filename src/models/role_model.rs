@@ -0,0 +1,62 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool};
+
+use super::base::DbBmc;
+
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct UserRoleModel {
+    pub username: String,
+    pub role: String,
+    pub granted_by: Option<String>,
+    pub granted_at: NaiveDateTime,
+}
+
+impl DbBmc for UserRoleModel {
+    const TABLE: &'static str = "user_management.user_roles";
+}
+
+pub async fn get_roles(pool: &PgPool, username: &str) -> ModelResult<Vec<UserRoleModel>> {
+    let roles = sqlx::query_as::<_, UserRoleModel>(&format!(
+        "SELECT * FROM {} WHERE username = $1 ORDER BY granted_at;",
+        UserRoleModel::TABLE
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await?;
+    Ok(roles)
+}
+
+/// Grants a role, returning the number of rows affected, 0 if the user already had it.
+pub async fn grant_role(
+    pool: &PgPool,
+    username: &str,
+    role: &str,
+    granted_by: Option<&str>,
+) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "INSERT INTO {} (username, role, granted_by) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING;",
+        UserRoleModel::TABLE
+    ))
+    .bind(username)
+    .bind(role)
+    .bind(granted_by)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
+
+pub async fn revoke_role(pool: &PgPool, username: &str, role: &str) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "DELETE FROM {} WHERE username = $1 AND role = $2;",
+        UserRoleModel::TABLE
+    ))
+    .bind(username)
+    .bind(role)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
@@ -16,6 +16,7 @@ impl DbBmc for SeenPostsModel {
     const TABLE: &'static str = "post_management.seen_posts";
 }
 
+#[allow(unused)]
 #[derive(Deserialize, Serialize, FromRow, Debug, Fields)]
 pub struct SeenPostCreateModel {
     pub post_id: i64,
@@ -34,3 +35,28 @@ pub async fn seen(pool: &PgPool, username: &str, post_id: i64) -> ModelResult<()
     .await?;
     Ok(())
 }
+
+/// Most time stored per seen post, so that repeated reports cannot grow it without bound.
+const MAX_TOTAL_DWELL_MS: i64 = 60 * 60 * 1000;
+
+/// Adds to the time the user spent on a post they have seen, up to `MAX_TOTAL_DWELL_MS`,
+/// returning the number of rows affected.
+pub async fn add_dwell(
+    pool: &PgPool,
+    username: &str,
+    post_id: i64,
+    dwell_ms: i64,
+) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET dwell_ms = LEAST(dwell_ms + $3, {}) WHERE username = $1 AND post_id = $2;",
+        SeenPostsModel::TABLE,
+        MAX_TOTAL_DWELL_MS
+    ))
+    .bind(username)
+    .bind(post_id)
+    .bind(dwell_ms)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
@@ -0,0 +1,87 @@
+use chrono::NaiveDateTime;
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool, Postgres, Transaction};
+
+use super::base::{self, DbBmc};
+
+#[derive(Deserialize, Serialize, FromRow, Debug)]
+pub struct TotpModel {
+    pub id: i64,
+    pub username: String,
+    pub secret: String,
+    pub last_used_step: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub enabled_at: Option<NaiveDateTime>,
+}
+
+impl DbBmc for TotpModel {
+    const TABLE: &'static str = "user_management.totp";
+}
+
+pub async fn get_totp(pool: &PgPool, username: &str) -> ModelResult<Option<TotpModel>> {
+    let totp = sqlx::query_as::<_, TotpModel>(&format!(
+        "SELECT * FROM {} WHERE username = $1;",
+        TotpModel::TABLE
+    ))
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+    Ok(totp)
+}
+
+/// Returns true if the user has confirmed a totp secret.
+pub async fn is_totp_enabled(pool: &PgPool, username: &str) -> ModelResult<bool> {
+    let totp = get_totp(pool, username).await?;
+    Ok(totp.is_some_and(|t| t.enabled_at.is_some()))
+}
+
+/// Stores a new, not yet enabled, secret for the user, replacing any pending one.
+pub async fn upsert_pending_totp(pool: &PgPool, username: &str, secret: &str) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "INSERT INTO {} (username, secret) VALUES ($1, $2)
+        ON CONFLICT (username) DO UPDATE
+        SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = now(), enabled_at = NULL;",
+        TotpModel::TABLE
+    ))
+    .bind(username)
+    .bind(secret)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn enable_totp(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    step: i64,
+) -> ModelResult<()> {
+    sqlx::query(&format!(
+        "UPDATE {} SET enabled_at = now(), last_used_step = $2 WHERE username = $1;",
+        TotpModel::TABLE
+    ))
+    .bind(username)
+    .bind(step)
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Records the step of an accepted code, returning false if it was already used so the code cannot be replayed.
+pub async fn use_totp_step(pool: &PgPool, username: &str, step: i64) -> ModelResult<bool> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET last_used_step = $2
+        WHERE username = $1 AND (last_used_step IS NULL OR last_used_step < $2);",
+        TotpModel::TABLE
+    ))
+    .bind(username)
+    .bind(step)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+pub async fn delete_totp(pool: &PgPool, username: &str) -> ModelResult<u64> {
+    base::delete::<TotpModel, _>("username", username, pool).await
+}
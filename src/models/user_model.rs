@@ -1,4 +1,5 @@
 use super::base::{self, DbBmc};
+use super::role_model::UserRoleModel;
 use chrono::NaiveDateTime;
 use lib_hash::hash_scheme::HashScheme;
 use lib_models::error::ModelResult;
@@ -17,8 +18,13 @@ pub struct UserModel {
     pub pwd_hash: String,
     pub pwd_salt: String,
     pub hash_scheme: HashScheme,
+    pub pepper_id: Option<i16>,
     pub created_at: NaiveDateTime,
     pub deactivated_at: Option<NaiveDateTime>,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub verification_sent_at: Option<NaiveDateTime>,
+    pub suspended_until: Option<NaiveDateTime>,
+    pub suspension_reason: Option<String>,
 }
 
 impl DbBmc for UserModel {
@@ -35,8 +41,18 @@ pub struct CreateUserModel {
     pub pwd_salt: String,
     // pub jwt_salt: String,
     pub hash_scheme: HashScheme,
+    pub pepper_id: Option<i16>,
 }
 
+#[derive(Fields)]
+pub struct UpdatePasswordModel {
+    pub pwd_hash: String,
+    pub pwd_salt: String,
+    pub hash_scheme: HashScheme,
+    pub pepper_id: Option<i16>,
+}
+
+#[allow(unused)]
 #[derive(Serialize, Validate, FromRow)]
 pub struct ReadUserModel {
     pub first_name: String,
@@ -97,3 +113,219 @@ pub async fn get_user_id(username: &str, db: &PgPool) -> ModelResult<Option<i64>
     .await?;
     Ok(id)
 }
+
+/// Replaces the password hash, salt, scheme and pepper of a user, returning the number of rows affected.
+pub async fn update_password(
+    username: &str,
+    data: UpdatePasswordModel,
+    db: &PgPool,
+) -> ModelResult<u64> {
+    let rows_affected = sqlb::update()
+        .table(UserModel::TABLE)
+        // all fields so that pepper_id is cleared when peppering is disabled
+        .data(data.all_fields())
+        .and_where_eq("username", username)
+        .exec(db)
+        .await?;
+    Ok(rows_affected)
+}
+
+/// Invalidates every session jwt of a user issued before now, returning the number of rows affected.
+/// Truncated to the second like the expiry of a jwt, so a session issued right after is still valid.
+pub async fn invalidate_sessions(username: &str, db: &PgPool) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET sessions_valid_after = date_trunc('second', now()) WHERE username = $1",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
+
+/// Looks up the username registered with an email, ignoring case.
+pub async fn get_username_by_email(email: &str, db: &PgPool) -> ModelResult<Option<String>> {
+    let username = sqlx::query_scalar::<_, String>(&format!(
+        "SELECT username FROM {} WHERE lower(email) = lower($1)",
+        UserModel::TABLE
+    ))
+    .bind(email)
+    .fetch_optional(db)
+    .await?;
+    Ok(username)
+}
+
+/// Looks up the username of a user by their username or email, ignoring case.
+/// A username match is preferred in case a username looks like another user's email.
+pub async fn get_username_by_identifier(
+    identifier: &str,
+    db: &PgPool,
+) -> ModelResult<Option<String>> {
+    let username = sqlx::query_scalar::<_, String>(&format!(
+        "SELECT username FROM {} WHERE lower(username) = lower($1) OR lower(email) = lower($1)
+        ORDER BY lower(username) = lower($1) DESC LIMIT 1",
+        UserModel::TABLE
+    ))
+    .bind(identifier)
+    .fetch_optional(db)
+    .await?;
+    Ok(username)
+}
+
+#[derive(FromRow, Debug)]
+pub struct EmailVerificationModel {
+    pub username: String,
+    pub email: String,
+    pub email_verified_at: Option<NaiveDateTime>,
+}
+
+pub async fn get_email_verification(
+    username: &str,
+    db: &PgPool,
+) -> ModelResult<Option<EmailVerificationModel>> {
+    let model = sqlx::query_as::<_, EmailVerificationModel>(&format!(
+        "SELECT username, email, email_verified_at FROM {} WHERE username = $1",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .fetch_optional(db)
+    .await?;
+    Ok(model)
+}
+
+pub async fn is_email_verified(username: &str, db: &PgPool) -> ModelResult<bool> {
+    let verified = sqlx::query_scalar::<_, bool>(&format!(
+        "SELECT email_verified_at IS NOT NULL FROM {} WHERE username = $1",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .fetch_optional(db)
+    .await?;
+    Ok(verified.unwrap_or(false))
+}
+
+/// Records that a verification email is being sent, unless one was sent within the last `interval_secs`.
+/// Returns false when rate limited.
+pub async fn try_set_verification_sent(
+    username: &str,
+    interval_secs: i64,
+    db: &PgPool,
+) -> ModelResult<bool> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET verification_sent_at = now() WHERE username = $1
+        AND (verification_sent_at IS NULL OR verification_sent_at <= now() - make_interval(secs => $2))",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .bind(interval_secs as f64)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+/// Seconds until another verification email may be sent, 0 if one may be sent now.
+pub async fn verification_retry_after(
+    username: &str,
+    interval_secs: i64,
+    db: &PgPool,
+) -> ModelResult<i64> {
+    let secs = sqlx::query_scalar::<_, Option<i64>>(&format!(
+        "SELECT CEIL(EXTRACT(EPOCH FROM verification_sent_at + make_interval(secs => $2) - now()))::bigint
+        FROM {} WHERE username = $1",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .bind(interval_secs as f64)
+    .fetch_optional(db)
+    .await?
+    .flatten();
+    Ok(secs.unwrap_or(0).max(0))
+}
+
+/// Marks the email verified, only if it is still the email the link was sent to.
+/// Returns the number of rows affected.
+pub async fn verify_email(username: &str, email: &str, db: &PgPool) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET email_verified_at = now() WHERE username = $1 AND email = $2 AND email_verified_at IS NULL",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .bind(email)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
+
+/// SQL condition for a user whose suspension is in effect, for the users table aliased as `alias`.
+/// Timed suspensions end on their own once suspended_until passes.
+pub fn active_suspension_sql(alias: &str) -> String {
+    format!(
+        "({0}.deactivated_at IS NOT NULL AND ({0}.suspended_until IS NULL OR {0}.suspended_until > now()))",
+        alias
+    )
+}
+
+/// What a request needs to know about the account behind its token, looked up once per request.
+#[derive(FromRow, Debug)]
+pub struct CtxUserModel {
+    pub id: i64,
+    pub suspended: bool,
+    pub suspended_until: Option<NaiveDateTime>,
+    pub suspension_reason: Option<String>,
+    /// Session jwts issued before this are no longer valid.
+    pub sessions_valid_after: Option<NaiveDateTime>,
+    pub roles: Vec<String>,
+}
+
+pub async fn get_ctx_user(username: &str, db: &PgPool) -> ModelResult<Option<CtxUserModel>> {
+    let user = sqlx::query_as::<_, CtxUserModel>(&format!(
+        "SELECT u.id, {} AS suspended, u.suspended_until, u.suspension_reason, u.sessions_valid_after,
+            COALESCE(array_agg(r.role) FILTER (WHERE r.role IS NOT NULL), '{{}}') AS roles
+        FROM {} u LEFT JOIN {} r ON r.username = u.username
+        WHERE u.username = $1
+        GROUP BY u.id",
+        active_suspension_sql("u"),
+        UserModel::TABLE,
+        UserRoleModel::TABLE
+    ))
+    .bind(username)
+    .fetch_optional(db)
+    .await?;
+    Ok(user)
+}
+
+/// Suspends a user until `until`, or permanently when None, returning the number of rows affected.
+pub async fn suspend_user(
+    username: &str,
+    until: Option<NaiveDateTime>,
+    reason: &str,
+    db: &PgPool,
+) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET deactivated_at = now(), suspended_until = $2, suspension_reason = $3 WHERE username = $1",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .bind(until)
+    .bind(reason)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
+
+/// Lifts a suspension, returning the number of rows affected.
+pub async fn lift_suspension(username: &str, db: &PgPool) -> ModelResult<u64> {
+    let rows_affected = sqlx::query(&format!(
+        "UPDATE {} SET deactivated_at = NULL, suspended_until = NULL, suspension_reason = NULL WHERE username = $1",
+        UserModel::TABLE
+    ))
+    .bind(username)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(rows_affected)
+}
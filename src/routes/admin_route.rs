@@ -0,0 +1,150 @@
+use std::marker::PhantomData;
+
+use axum::{
+    extract::{Path, State},
+    middleware::from_fn,
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use ctx::{AdminRole, HasRole, ModeratorRole, Role};
+use lib_routes::{
+    error::{RouteError, RouterResult},
+    nested_route::NestedRoute,
+};
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    libs::validation::validate_struct,
+    middleware::role_mw::require_role,
+    models::{
+        content_model::set_post_deactivated,
+        role_model::{get_roles, grant_role, revoke_role, UserRoleModel},
+        user_model::{get_user_id, lift_suspension, suspend_user},
+    },
+    AppState,
+};
+
+/// Moderation and admin endpoints. Every route needs the moderator role, role management and suspensions need admin.
+pub struct AdminRoute;
+
+impl NestedRoute<AppState> for AdminRoute {
+    const PATH: &'static str = "/admin";
+    fn router() -> Router<AppState> {
+        Router::new()
+            .route("/posts/:post_id/hide", post(hide_post).delete(unhide_post))
+            .route("/users/:username/roles", get(list_roles))
+            .route(
+                "/users/:username/roles/:role",
+                put(add_role).delete(remove_role),
+            )
+            .route("/users/:username/suspension", put(suspend).delete(lift))
+            .route_layer(from_fn(require_role::<ModeratorRole>))
+    }
+}
+
+/// Hides a post from feeds without deleting it.
+async fn hide_post(State(s): State<AppState>, Path(post_id): Path<i64>) -> RouterResult<()> {
+    if set_post_deactivated(&s.pool, post_id, true).await? == 0 {
+        return Err(RouteError::Validation("Post not found".to_string()));
+    }
+    s.recommender.remove_post(post_id);
+    Ok(())
+}
+
+async fn unhide_post(State(s): State<AppState>, Path(post_id): Path<i64>) -> RouterResult<()> {
+    if set_post_deactivated(&s.pool, post_id, false).await? == 0 {
+        return Err(RouteError::Validation("Post not found".to_string()));
+    }
+    // its interactions are back in the model at the next retrain
+    s.recommender.add_post(post_id);
+    Ok(())
+}
+
+async fn list_roles(
+    _: HasRole<AdminRole>,
+    State(s): State<AppState>,
+    Path(username): Path<String>,
+) -> RouterResult<Json<Vec<UserRoleModel>>> {
+    let roles = get_roles(&s.pool, &username).await?;
+    Ok(Json(roles))
+}
+
+async fn add_role(
+    HasRole(ctx, PhantomData): HasRole<AdminRole>,
+    State(s): State<AppState>,
+    Path((username, role)): Path<(String, String)>,
+) -> RouterResult<()> {
+    let role = role.parse::<Role>()?;
+    if get_user_id(&username, &s.pool).await?.is_none() {
+        return Err(RouteError::Validation("User not found".to_string()));
+    }
+    grant_role(&s.pool, &username, role.as_str(), Some(ctx.username())).await?;
+    Ok(())
+}
+
+async fn remove_role(
+    HasRole(ctx, PhantomData): HasRole<AdminRole>,
+    State(s): State<AppState>,
+    Path((username, role)): Path<(String, String)>,
+) -> RouterResult<()> {
+    let role = role.parse::<Role>()?;
+    // keeps at least one admin able to manage roles
+    if role == Role::Admin && username == ctx.username() {
+        return Err(RouteError::Validation(
+            "Admins cannot remove their own admin role".to_string(),
+        ));
+    }
+    revoke_role(&s.pool, &username, role.as_str()).await?;
+    Ok(())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct SuspendModel {
+    #[validate(length(min = 1, max = 255, message = "Invalid reason length"))]
+    reason: String,
+    /// Suspends permanently when not set.
+    #[validate(range(min = 1, max = 87600, message = "Invalid duration"))]
+    duration_hours: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct SuspensionModel {
+    suspended_until: Option<NaiveDateTime>,
+}
+
+/// Suspends a user for `duration_hours`, or permanently, replacing any current suspension.
+/// They are refused at login and on every authenticated request, and their posts are hidden from feeds.
+async fn suspend(
+    HasRole(ctx, PhantomData): HasRole<AdminRole>,
+    State(s): State<AppState>,
+    Path(username): Path<String>,
+    Json(body): Json<SuspendModel>,
+) -> RouterResult<Json<SuspensionModel>> {
+    validate_struct(&body)?;
+    if username == ctx.username() {
+        return Err(RouteError::Validation(
+            "Admins cannot suspend themselves".to_string(),
+        ));
+    }
+    let suspended_until = body
+        .duration_hours
+        .map(|hours| Utc::now().naive_utc() + TimeDelta::hours(hours));
+    if suspend_user(&username, suspended_until, body.reason.trim(), &s.pool).await? == 0 {
+        return Err(RouteError::Validation("User not found".to_string()));
+    }
+    Ok(Json(SuspensionModel { suspended_until }))
+}
+
+async fn lift(
+    _: HasRole<AdminRole>,
+    State(s): State<AppState>,
+    Path(username): Path<String>,
+) -> RouterResult<()> {
+    if lift_suspension(&username, &s.pool).await? == 0 {
+        return Err(RouteError::Validation("User not found".to_string()));
+    }
+    Ok(())
+}
@@ -1,19 +1,41 @@
+use crate::libs::env::env_or;
 use crate::libs::validation::{validate_struct, RE_NAME, RE_USERNAME};
-use crate::middleware::auth_mw::{AUTH_TOKEN, JWT_SECRET};
-use crate::models::user_model::{username_or_email_exists, CreateUserModel, UserModel};
+use crate::middleware::auth_mw::{AUTH_TOKEN, JWT_SECRET, TWO_FACTOR_SECRET};
+use crate::models::access_token_model::revoke_all_access_tokens;
+use crate::models::password_reset_model::{
+    consume_reset_token, create_reset_token, reset_retry_after, try_set_reset_requested,
+    use_reset_tokens,
+};
+use crate::models::totp_model::is_totp_enabled;
+use crate::models::user_model::{
+    get_username_by_email, get_username_by_identifier, invalidate_sessions,
+    username_or_email_exists, CreateUserModel, UserModel,
+};
+use crate::services::email_verification::{confirm_verification_link, send_verification_email};
+use crate::services::login_throttle::{
+    check_login_allowed, clear_login_failures, client_ip, record_login_failure,
+};
+use crate::services::mailer::Email;
+use crate::services::password::{get_hash_model, set_password, verify_password};
+use crate::services::suspension::check_not_suspended;
+use crate::services::two_factor::{verify_recovery_code, verify_totp_code};
 use crate::AppState;
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::routing::post;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
 use axum::{Json, Router};
+use chrono::{DateTime, TimeDelta, Utc};
 use jwt::{JWT, JWT_LIFE_IN_MINUTES};
-use lib_hash::hash_scheme::{HashScheme, Hasher};
-use lib_hash::hashers::argon2_v01::Argon2V01;
+use lib_hash::hash_scheme::HashScheme;
+use lib_hash::pepper;
+use lib_hash::token::{generate_token, token_digest};
 use lib_routes::error::{RouteError, RouterResult};
 use lib_routes::nested_route::NestedRoute;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlb::Fields;
-use sqlx::prelude::FromRow;
+use std::net::SocketAddr;
+use tower_cookies::cookie::{time::Duration, SameSite};
 use tower_cookies::{Cookie, Cookies};
 use validator::Validate;
 
@@ -25,6 +47,10 @@ impl NestedRoute<AppState> for AuthRoute {
         Router::new()
             .route("/signup", post(sign_up))
             .route("/login", post(log_in))
+            .route("/login/2fa", post(log_in_two_factor))
+            .route("/password/forgot", post(forgot_password))
+            .route("/password/reset", post(reset_password))
+            .route("/email/verify", get(verify_email))
     }
 }
 
@@ -64,70 +90,139 @@ pub async fn sign_up(
         return Err(RouteError::AlreadyTaken(taken));
     }
 
-    let hasher = Argon2V01;
-    let (pwd_hash, pwd_salt) = hasher.hash(&body.password)?;
+    let pepper = pepper::current();
+    let (pwd_hash, pwd_salt) = HashScheme::CURRENT
+        .hasher()
+        .hash_peppered(&body.password, pepper)?;
 
     let create_model = CreateUserModel {
-        username: body.username,
-        email: body.email,
+        username: body.username.clone(),
+        email: body.email.clone(),
         first_name: body.first_name,
         last_name: body.last_name,
         pwd_hash,
         pwd_salt: pwd_salt.to_string(),
-        hash_scheme: hasher.into(),
+        hash_scheme: HashScheme::CURRENT,
+        pepper_id: pepper.map(|p| p.id()),
     };
 
     let user_id = super::models::base::create::<UserModel, _>(create_model, &s.pool).await?;
-    s.ndarray_app_state
-        .lock()
-        .expect("err locking")
-        .add_user(user_id)
-        .expect("err adding user");
+    s.recommender.add_user(user_id);
+
+    // the account is usable without this, the user can ask for another email
+    if let Err(e) =
+        send_verification_email(&s.pool, s.mailer.as_ref(), &body.username, &body.email).await
+    {
+        println!(
+            "Could not send verification email to {}: {:?}",
+            body.username, e
+        );
+    }
 
     Ok(StatusCode::CREATED)
 }
 
 #[derive(Deserialize, Validate)]
 pub struct LoginModel {
-    #[validate(length(min = 5, max = 32, message = "Invalid username length"))]
-    #[validate(regex(path = "*RE_USERNAME"))]
-    pub username: String,
-    #[validate(length(min = 8, max = 32, message = "Invalid password length"))]
+    /// A username or an email, `username` is still accepted for older clients.
+    #[serde(alias = "username")]
+    #[validate(length(min = 1, max = 255, message = "Invalid username or email length"))]
+    pub identifier: String,
+    #[validate(length(min = 1, max = 64, message = "Invalid password length"))]
     pub password: String,
+    /// Also return the auth token in the response body, for clients that cannot use cookies.
+    #[serde(default)]
+    pub return_token: bool,
 }
 
-#[derive(FromRow, Fields, Debug)]
-pub struct HashModel {
-    username: String,
-    hash_scheme: HashScheme,
-    pwd_hash: String,
-    pwd_salt: String,
+#[derive(Serialize)]
+pub struct AuthTokenModel {
+    pub auth_token: String,
+    pub token_type: &'static str,
+    pub expires: DateTime<Utc>,
 }
 
-/// logs user in with username & password
+pub const TWO_FACTOR_CHALLENGE_LIFE_IN_MINUTES: i64 = 5;
+
+/// Returned with 202 Accepted instead of a session when the user has 2FA enabled.
+#[derive(Serialize)]
+pub struct TwoFactorChallengeModel {
+    pub challenge_token: String,
+    pub expires: DateTime<Utc>,
+}
+
+/// logs user in with username or email & password.
+/// Sets the auth cookie, and returns the token in the body when `return_token` is set.
+/// If the user has 2FA enabled a challenge token is returned instead, see `log_in_two_factor`.
+/// Repeated failures lock out the username and client ip, see `login_throttle`.
 pub async fn log_in(
     State(s): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     cookies: Cookies,
     Json(mut body): Json<LoginModel>,
-) -> RouterResult<()> {
+) -> RouterResult<Response> {
     validate_struct(&body)?;
 
-    body.username = body.username.trim().to_lowercase();
+    body.identifier = body.identifier.trim().to_lowercase();
     body.password = body.password.trim().to_string();
 
-    let option_hash = super::models::base::get_one::<UserModel, HashModel, _>(
-        "username",
-        &body.username,
-        &s.pool,
-    )
-    .await?;
+    // failures are counted against the account when it exists, so its username and email share a lockout
+    let username = get_username_by_identifier(&body.identifier, &s.pool)
+        .await?
+        .unwrap_or(body.identifier);
+
+    let ip = client_ip(addr, &headers);
+    check_login_allowed(&s.pool, &username, ip).await?;
 
-    let hash_model = option_hash.ok_or(RouteError::LoginFail)?;
+    let hash_model = match get_hash_model(&s.pool, &username).await? {
+        Some(hash_model) => verify_password(&s.pool, &hash_model, &body.password)
+            .await
+            .map(|_| hash_model),
+        None => Err(RouteError::LoginFail),
+    };
+    let hash_model = match hash_model {
+        Err(RouteError::LoginFail) => {
+            record_login_failure(&s.pool, &username, ip).await?;
+            return Err(RouteError::LoginFail);
+        }
+        r => r?,
+    };
+    check_not_suspended(&s.pool, &hash_model.username).await?;
+
+    if is_totp_enabled(&s.pool, &hash_model.username).await? {
+        let body = two_factor_challenge(hash_model.username)?;
+        return Ok((StatusCode::ACCEPTED, Json(body)).into_response());
+    }
+
+    clear_login_failures(&s.pool, &hash_model.username).await?;
+    issue_session(hash_model.username, &cookies, body.return_token)
+}
+
+/// Sets the auth cookie for a user who has fully logged in,
+/// returning the token in the body when `return_token` is set.
+fn issue_session(
+    username: String,
+    cookies: &Cookies,
+    return_token: bool,
+) -> RouterResult<Response> {
+    let result_jwt = set_session_cookie(username, cookies)?;
+
+    if !return_token {
+        return Ok(().into_response());
+    }
 
-    let hasher = hash_model.hash_scheme.hasher();
-    hasher.verify(&body.password, &hash_model.pwd_salt, &hash_model.pwd_hash)?;
+    let token = AuthTokenModel {
+        auth_token: result_jwt.to_string(),
+        token_type: "Bearer",
+        expires: *result_jwt.expires(),
+    };
+    Ok(Json(token).into_response())
+}
 
-    let result_jwt = JWT::new(hash_model.username.clone(), &JWT_SECRET)?;
+/// Signs a session jwt for the user and sets it as the auth cookie.
+pub(super) fn set_session_cookie(username: String, cookies: &Cookies) -> RouterResult<JWT> {
+    let result_jwt = JWT::new(username, &JWT_SECRET)?;
 
     let mut auth_cookie = Cookie::new(AUTH_TOKEN, result_jwt.to_string());
     let expires = tower_cookies::cookie::time::OffsetDateTime::now_utc()
@@ -136,5 +231,192 @@ pub async fn log_in(
     auth_cookie.set_path("/");
     cookies.add(auth_cookie);
 
+    Ok(result_jwt)
+}
+
+/// Signs a short lived challenge to exchange for a session with `log_in_two_factor`.
+pub(super) fn two_factor_challenge(username: String) -> RouterResult<TwoFactorChallengeModel> {
+    let challenge = JWT::with_lifetime(
+        username,
+        &TWO_FACTOR_SECRET,
+        TimeDelta::minutes(TWO_FACTOR_CHALLENGE_LIFE_IN_MINUTES),
+    )?;
+    Ok(TwoFactorChallengeModel {
+        challenge_token: challenge.to_string(),
+        expires: *challenge.expires(),
+    })
+}
+
+/// Carries the challenge of redirect based logins, e.g. OIDC, so that it is never put in a url.
+const TWO_FACTOR_CHALLENGE_COOKIE: &str = "two_factor_challenge";
+
+fn two_factor_cookie_path() -> String {
+    format!("{}/login/2fa", AuthRoute::PATH)
+}
+
+/// Sets the challenge as a short lived HttpOnly cookie only sent to `log_in_two_factor`.
+pub(super) fn set_challenge_cookie(challenge: &TwoFactorChallengeModel, cookies: &Cookies) {
+    let mut cookie = Cookie::new(
+        TWO_FACTOR_CHALLENGE_COOKIE,
+        challenge.challenge_token.clone(),
+    );
+    cookie.set_path(two_factor_cookie_path());
+    cookie.set_http_only(true);
+    // lax so the cookie is kept from the provider's top level redirect
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(Duration::minutes(TWO_FACTOR_CHALLENGE_LIFE_IN_MINUTES));
+    cookies.add(cookie);
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorLoginModel {
+    /// Falls back to the challenge cookie when not given, see `set_challenge_cookie`.
+    pub challenge_token: Option<String>,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+    #[serde(default)]
+    pub return_token: bool,
+}
+
+/// Second login step for users with 2FA, exchanging a challenge token and a code for a session.
+/// Wrong codes count towards the same lockout as wrong passwords.
+pub async fn log_in_two_factor(
+    State(s): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    cookies: Cookies,
+    Json(body): Json<TwoFactorLoginModel>,
+) -> RouterResult<Response> {
+    let challenge_token = body
+        .challenge_token
+        .or_else(|| {
+            cookies
+                .get(TWO_FACTOR_CHALLENGE_COOKIE)
+                .map(|c| c.value().to_string())
+        })
+        .ok_or(RouteError::LoginFail)?;
+    let challenge = JWT::parse_token(challenge_token)?;
+    challenge.validate_token(&TWO_FACTOR_SECRET)?;
+    let username = challenge.username();
+
+    let ip = client_ip(addr, &headers);
+    check_login_allowed(&s.pool, username, ip).await?;
+
+    let verified = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => verify_totp_code(&s.pool, username, code).await?,
+        (None, Some(code)) => verify_recovery_code(&s.pool, username, code).await?,
+        (None, None) => false,
+    };
+    if !verified {
+        record_login_failure(&s.pool, username, ip).await?;
+        return Err(RouteError::LoginFail);
+    }
+    check_not_suspended(&s.pool, username).await?;
+
+    clear_login_failures(&s.pool, username).await?;
+    cookies.remove(
+        Cookie::build(TWO_FACTOR_CHALLENGE_COOKIE)
+            .path(two_factor_cookie_path())
+            .into(),
+    );
+    issue_session(username.to_string(), &cookies, body.return_token)
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ForgotPasswordModel {
+    #[validate(
+        email(message = "Invalid email"),
+        length(min = 1, max = 255, message = "Invalid email length")
+    )]
+    pub email: String,
+}
+
+/// Emails a single use reset link if the email is registered.
+/// Responds the same either way so that registered emails cannot be discovered.
+/// Errs with RateLimited if a reset was requested for the email too recently, see PASSWORD_RESET_RESEND_SECONDS.
+pub async fn forgot_password(
+    State(s): State<AppState>,
+    Json(body): Json<ForgotPasswordModel>,
+) -> RouterResult<()> {
+    validate_struct(&body)?;
+
+    let email = body.email.trim().to_lowercase();
+    let resend_secs = env_or("PASSWORD_RESET_RESEND_SECONDS", 60);
+    if !try_set_reset_requested(&s.pool, &email, resend_secs).await? {
+        let retry_after = reset_retry_after(&s.pool, &email, resend_secs).await?;
+        return Err(RouteError::RateLimited(retry_after));
+    }
+
+    let Some(username) = get_username_by_email(body.email.trim(), &s.pool).await? else {
+        return Ok(());
+    };
+
+    let token = generate_token();
+    let life_in_minutes = env_or("PASSWORD_RESET_LIFE_IN_MINUTES", 30);
+    create_reset_token(&s.pool, &username, &token_digest(&token), life_in_minutes).await?;
+
+    let app_url = env_or("APP_URL", "http://localhost:3000".to_string());
+    let email = Email {
+        to: body.email,
+        subject: "Reset your Flex Forum password".to_string(),
+        body: format!(
+            "Hi {},\n\nUse the link below to reset your password. It expires in {} minutes.\n\n{}/reset-password?token={}\n\nIf you did not ask to reset your password you can ignore this email.",
+            username, life_in_minutes, app_url, token
+        ),
+    };
+    s.mailer.send(email).await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ResetPasswordModel {
+    #[validate(length(min = 1, max = 128, message = "Invalid token"))]
+    pub token: String,
+    #[validate(length(min = 1, max = 64, message = "Invalid password length"))]
+    pub new_password: String,
+}
+
+/// Sets a new password using a token from `forgot_password`, consuming all of the user's reset tokens.
+pub async fn reset_password(
+    State(s): State<AppState>,
+    Json(body): Json<ResetPasswordModel>,
+) -> RouterResult<()> {
+    validate_struct(&body)?;
+
+    let username = consume_reset_token(&s.pool, &token_digest(body.token.trim()))
+        .await?
+        .ok_or(RouteError::Validation(
+            "Invalid or expired reset token".to_string(),
+        ))?;
+
+    set_password(&s.pool, &username, body.new_password.trim()).await?;
+    use_reset_tokens(&s.pool, &username).await?;
+    revoke_all_access_tokens(&s.pool, &username).await?;
+    invalidate_sessions(&username, &s.pool).await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailQuery {
+    pub username: String,
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Verifies the user's email from the signed link sent by `send_verification_email`.
+pub async fn verify_email(
+    State(s): State<AppState>,
+    Query(query): Query<VerifyEmailQuery>,
+) -> RouterResult<()> {
+    let verified =
+        confirm_verification_link(&s.pool, &query.username, query.expires, &query.signature)
+            .await?;
+    if !verified {
+        return Err(RouteError::Validation(
+            "Invalid or expired verification link".to_string(),
+        ));
+    }
     Ok(())
 }
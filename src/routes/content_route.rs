@@ -2,12 +2,13 @@ use axum::{
     body::{Body, Bytes},
     extract::{Path, State},
     http::StatusCode,
-    routing::{delete, get, post},
+    middleware::from_fn,
+    routing::{get, post},
     Json, Router,
 };
 use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
 use chrono::NaiveDateTime;
-use ctx::Ctx;
+use ctx::{AuthUser, Ctx, FeedReadScope, InteractionsWriteScope, PostsWriteScope};
 use lib_multipart::validate_content_type;
 use lib_routes::error::{RouteError, RouterResult};
 // use ctx::Ctx;
@@ -15,6 +16,8 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 use crate::{
+    libs::validation::validate_struct,
+    middleware::scope_mw::require_scope,
     models::{
         base::{self},
         content_model::{
@@ -23,9 +26,9 @@ use crate::{
         following_model::is_following,
         likes_model::{get_num_likes, is_liked, LikePost, LikesModel},
         profile_picture_model::ProfilePictureModel,
-        seen_posts_model::seen,
-        user_model::get_user_id,
+        seen_posts_model::{add_dwell, seen},
     },
+    services::email_verification::{require_verified, GatedAction},
     services::s3::{s3_delete_post, s3_download_post, s3_upload_post, s3_upload_profile_picture},
     AppState,
 };
@@ -36,13 +39,36 @@ pub struct ContentRoute;
 impl NestedRoute<AppState> for ContentRoute {
     const PATH: &'static str = "/content";
     fn router() -> axum::Router<AppState> {
+        let posts_write = || from_fn(require_scope::<PostsWriteScope>);
+        let feed_read = || from_fn(require_scope::<FeedReadScope>);
+        let interactions_write = || from_fn(require_scope::<InteractionsWriteScope>);
         Router::new()
-            .route("/images", post(upload_images_post))
-            .route("/:post_type/:username/:post_id/:content_id", get(download))
-            .route("/workouts", post(upload_workout_post))
-            .route("/posts/:created_at", get(get_post_by_time))
-            .route("/like/:post_id", post(like_post))
-            .route("/like/:post_id", delete(unlike_post))
+            .route(
+                "/images",
+                post(upload_images_post).route_layer(posts_write()),
+            )
+            .route(
+                "/:post_type/:username/:post_id/:content_id",
+                get(download).route_layer(feed_read()),
+            )
+            .route(
+                "/workouts",
+                post(upload_workout_post).route_layer(posts_write()),
+            )
+            .route(
+                "/posts/:created_at",
+                get(get_post_by_time).route_layer(feed_read()),
+            )
+            .route(
+                "/like/:post_id",
+                post(like_post)
+                    .delete(unlike_post)
+                    .route_layer(interactions_write()),
+            )
+            .route(
+                "/dwell/:post_id",
+                post(record_dwell).route_layer(interactions_write()),
+            )
             .route("/profile-picture", post(upload_profile_picture))
     }
 }
@@ -56,13 +82,14 @@ struct UploadImageMulipart {
 }
 
 const IMAGE_CONTENT_TYPES: &[&str] = &["image/jpeg", "image/jpg"];
-const JSON_CONTENT_TYPE: &'static str = "application/json";
+const JSON_CONTENT_TYPE: &str = "application/json";
 
 async fn upload_images_post(
     ctx: Ctx,
     State(s): State<AppState>,
     TypedMultipart(upload): TypedMultipart<UploadImageMulipart>,
 ) -> RouterResult<StatusCode> {
+    require_verified(&s.pool, ctx.username(), GatedAction::Post).await?;
     validate_content_type(&upload.image1, IMAGE_CONTENT_TYPES)?;
     if let Some(fd) = &upload.image2 {
         validate_content_type(fd, IMAGE_CONTENT_TYPES)?;
@@ -72,10 +99,10 @@ async fn upload_images_post(
     }
 
     let mut counter = 1;
-    if let Some(_) = upload.image2 {
+    if upload.image2.is_some() {
         counter += 1;
     }
-    if let Some(_) = upload.image3 {
+    if upload.image3.is_some() {
         counter += 1;
     }
 
@@ -83,7 +110,7 @@ async fn upload_images_post(
 
     // let transaction = pool.begin().await?;
     let post = content_model::CreatePostModel {
-        username: ctx.jwt().username().to_string(),
+        username: ctx.username().to_string(),
         num_images: counter,
         description: upload.description,
         post_type: PostType::Images,
@@ -92,7 +119,7 @@ async fn upload_images_post(
         super::models::base::create_with_transaction::<ContentModel, _>(post, &mut transaction)
             .await?;
     let mut counter = 1;
-    let username = ctx.jwt().username();
+    let username = ctx.username();
 
     s3_upload_post(
         &s.s3_client,
@@ -118,7 +145,7 @@ async fn upload_images_post(
         )
         .await;
 
-        if let Err(_) = res {
+        if res.is_err() {
             s3_delete_post(&s.s3_client, username, post_id, counter - 1).await?;
         }
 
@@ -138,7 +165,7 @@ async fn upload_images_post(
         )
         .await;
 
-        if let Err(_) = res {
+        if res.is_err() {
             s3_delete_post(&s.s3_client, username, post_id, counter - 2).await?;
             s3_delete_post(&s.s3_client, username, post_id, counter - 1).await?;
         }
@@ -146,19 +173,15 @@ async fn upload_images_post(
         res?;
     }
 
-    s.ndarray_app_state
-        .lock()
-        .unwrap()
-        .add_post(post_id)
-        .unwrap();
-
     transaction.commit().await?;
+    // only once committed, a rolled back post must not end up in the model
+    s.recommender.add_post(post_id);
 
     Ok(StatusCode::CREATED)
 }
 
 async fn download(
-    _ctx: Ctx,
+    _: Ctx,
     Path((post_type, username, post_id, content_id)): Path<(PostType, String, i64, i64)>,
     State(s): State<AppState>,
 ) -> RouterResult<Body> {
@@ -205,6 +228,7 @@ async fn upload_workout_post(
     State(s): State<AppState>,
     body: Json<UploadWorkout>,
 ) -> RouterResult<StatusCode> {
+    require_verified(&s.pool, ctx.username(), GatedAction::Post).await?;
     if let Err(e) = body.validate() {
         return Err(RouteError::Validation(e.to_string()));
     }
@@ -214,7 +238,7 @@ async fn upload_workout_post(
     }
 
     let post = content_model::CreatePostModel {
-        username: ctx.jwt().username().to_string(),
+        username: ctx.username().to_string(),
         num_images: 0,
         description: body.description.clone(),
         post_type: PostType::Workout,
@@ -234,7 +258,7 @@ async fn upload_workout_post(
     s3_upload_post(
         &s.s3_client,
         bytes,
-        ctx.jwt().username(),
+        ctx.username(),
         post_id,
         1,
         JSON_CONTENT_TYPE,
@@ -243,6 +267,7 @@ async fn upload_workout_post(
     .await?;
 
     transaction.commit().await?;
+    s.recommender.add_post(post_id);
 
     Ok(StatusCode::CREATED)
 }
@@ -257,22 +282,20 @@ struct PostCard {
 }
 
 async fn get_post_by_time(
-    ctx: Ctx,
+    AuthUser { id: user_id, ctx }: AuthUser,
     State(s): State<AppState>,
     Path(created_at): Path<NaiveDateTime>,
 ) -> RouterResult<Json<Vec<PostCard>>> {
     // let posts = get_three_older(&s.pool, &created_at).await?;
-    let mut posts = get_ten_unseen_older(&s.pool, &created_at, ctx.jwt().username()).await?;
-    let user_id = get_user_id(ctx.jwt().username(), &s.pool).await?.unwrap();
-    // .unwrap_or(Err(RouteError::Unauthorized)?);
+    let mut posts = get_ten_unseen_older(&s.pool, &created_at, ctx.username()).await?;
 
-    if posts.len() > 0 {
-        sort_by_predicted(&mut posts, &s, 3, user_id);
+    if !posts.is_empty() {
+        sort_by_predicted(&mut posts, &s, 3, user_id, ctx.username()).await?;
 
         // Mark all posts as seen so that they do not get recommended again.
         // Will likely change in the future so that interactions will only count as seen, or number of times recommended.
         for p in &posts {
-            seen(&s.pool, ctx.jwt().username(), p.id).await?;
+            seen(&s.pool, ctx.username(), p.id).await?;
         }
     }
     // if the posts length is 0 then they have seen all recommended posts, so just give them older already seen content again
@@ -282,18 +305,18 @@ async fn get_post_by_time(
 
     let mut post_cards: Vec<PostCard> = Vec::with_capacity(posts.len());
 
-    for i in 0..posts.len() {
-        let post_id = posts[i].id;
+    for post in &posts {
+        let post_id = post.id;
         let num_likes = get_num_likes(&s.pool, post_id).await?;
         let like = LikePost {
             post_id,
-            username: ctx.jwt().username().to_string(),
+            username: ctx.username().to_string(),
         };
         let is_liked = is_liked(&s.pool, like).await?;
         // let is_following = false;
-        let is_following = is_following(&s.pool, ctx.jwt().username(), &posts[i].username).await?;
+        let is_following = is_following(&s.pool, ctx.username(), &post.username).await?;
         let card = PostCard {
-            content_model: posts[i].clone(),
+            content_model: post.clone(),
             is_liked,
             num_likes,
             is_following,
@@ -305,21 +328,23 @@ async fn get_post_by_time(
 }
 
 async fn like_post(
-    ctx: Ctx,
+    AuthUser { id: user_id, ctx }: AuthUser,
     State(s): State<AppState>,
     Path(post_id): Path<i64>,
 ) -> RouterResult<()> {
+    require_verified(&s.pool, ctx.username(), GatedAction::Like).await?;
     let like: LikePost = LikePost {
         post_id,
-        username: ctx.jwt().username().to_string(),
+        username: ctx.username().to_string(),
     };
     super::models::base::create::<LikesModel, LikePost>(like, &s.pool).await?;
-    seen(&s.pool, ctx.jwt().username(), post_id).await?;
+    seen(&s.pool, ctx.username(), post_id).await?;
+    s.recommender.like(user_id, post_id, true);
     Ok(())
 }
 
 async fn unlike_post(
-    ctx: Ctx,
+    AuthUser { id: user_id, ctx }: AuthUser,
     State(s): State<AppState>,
     Path(post_id): Path<i64>,
 ) -> RouterResult<()> {
@@ -327,10 +352,32 @@ async fn unlike_post(
         "post_id",
         post_id,
         "username",
-        ctx.jwt().username(),
+        ctx.username(),
         &s.pool,
     )
     .await?;
+    s.recommender.like(user_id, post_id, false);
+    Ok(())
+}
+
+#[derive(Deserialize, Validate)]
+struct DwellModel {
+    /// Capped so that a post left open does not count as much interest.
+    #[validate(range(min = 1, max = 600000, message = "Invalid dwell time"))]
+    dwell_ms: i64,
+}
+
+/// Adds the time spent viewing a post from the feed, the post must have been seen.
+async fn record_dwell(
+    ctx: Ctx,
+    State(s): State<AppState>,
+    Path(post_id): Path<i64>,
+    Json(body): Json<DwellModel>,
+) -> RouterResult<()> {
+    validate_struct(&body)?;
+    if add_dwell(&s.pool, ctx.username(), post_id, body.dwell_ms).await? == 0 {
+        return Err(RouteError::Validation("Post not seen".to_string()));
+    }
     Ok(())
 }
 
@@ -344,11 +391,12 @@ async fn upload_profile_picture(
     State(s): State<AppState>,
     TypedMultipart(upload): TypedMultipart<UploadProfileImageMulipart>,
 ) -> RouterResult<()> {
+    ctx.require_session()?;
     validate_content_type(&upload.image, IMAGE_CONTENT_TYPES)?;
 
     let model = ProfilePictureModel {
         id: 0,
-        username: ctx.jwt().username().to_string(),
+        username: ctx.username().to_string(),
     };
 
     let mut transaction = s.pool.begin().await?;
@@ -359,13 +407,13 @@ async fn upload_profile_picture(
     )
     .await?;
 
-    if items.len() == 0 {
+    if items.is_empty() {
         base::create_with_transaction::<ProfilePictureModel, _>(model, &mut transaction).await?;
     }
 
     s3_upload_profile_picture(
         &s.s3_client,
-        ctx.jwt().username(),
+        ctx.username(),
         upload.image.contents,
         upload.image.metadata.content_type.unwrap(), // content type validated above
     )
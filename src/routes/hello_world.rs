@@ -4,7 +4,7 @@ use lib_routes::nested_route::NestedRoute;
 
 pub struct HelloWorldRoute;
 
-impl<'a> NestedRoute<AppState> for HelloWorldRoute {
+impl NestedRoute<AppState> for HelloWorldRoute {
     const PATH: &'static str = "/helloworld";
     fn router() -> axum::Router<AppState> {
         Router::new().route("/", get(hello_world))
@@ -1,18 +1,25 @@
 use lib_routes::nested_route::NestedRoute;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use self::{auth_route::AuthRoute, hello_world::HelloWorldRoute, users_route::UserRoute};
+use self::{
+    admin_route::AdminRoute, auth_route::AuthRoute, hello_world::HelloWorldRoute,
+    oidc_route::OidcRoute, token_route::TokenRoute, two_factor_route::TwoFactorRoute,
+    users_route::UserRoute,
+};
 use crate::{
     middleware::{
         auth_mw::{ctx_resolver, validate_auth},
         logger_mw::logger,
     },
     models,
-    services::ndarray::NDArrayAppState,
+    services::{
+        cold_start::ColdStartConfig, feed_ranking::FeedRankingConfig, mailer::Mailer,
+        oidc::OidcProvider, recommender::RecommenderHandle,
+    },
 };
 
 use axum::{
-    middleware::{from_fn, map_response},
+    middleware::{from_fn, from_fn_with_state, map_response},
     Router,
 };
 use content_route::ContentRoute;
@@ -20,17 +27,27 @@ use exercise_preset_route::ExercisePresetRoute;
 use sqlx::{Pool, Postgres};
 use tower_cookies::CookieManagerLayer;
 
+mod admin_route;
 mod auth_route;
 mod content_route;
 mod exercise_preset_route;
 mod hello_world;
+mod oidc_route;
+mod token_route;
+mod two_factor_route;
 mod users_route;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub pool: Pool<Postgres>,
     pub s3_client: aws_sdk_s3::Client,
-    pub ndarray_app_state: Arc<Mutex<NDArrayAppState>>,
+    pub recommender: Arc<RecommenderHandle>,
+    /// Scores posts in the feed that the recommender cannot.
+    pub cold_start: ColdStartConfig,
+    pub feed_ranking: FeedRankingConfig,
+    pub mailer: Arc<dyn Mailer>,
+    /// None when OIDC login is not configured.
+    pub oidc: Option<Arc<OidcProvider>>,
 }
 
 pub fn create_routes(app_state: AppState) -> Router {
@@ -38,10 +55,14 @@ pub fn create_routes(app_state: AppState) -> Router {
         .nest(HelloWorldRoute::PATH, HelloWorldRoute::router())
         .nest(UserRoute::PATH, UserRoute::router())
         .nest(ContentRoute::PATH, ContentRoute::router())
+        .nest(TwoFactorRoute::PATH, TwoFactorRoute::router())
+        .nest(TokenRoute::PATH, TokenRoute::router())
+        .nest(AdminRoute::PATH, AdminRoute::router())
         .layer(from_fn(validate_auth))
         .nest(ExercisePresetRoute::PATH, ExercisePresetRoute::router())
         .nest(AuthRoute::PATH, AuthRoute::router())
-        .layer(from_fn(ctx_resolver))
+        .nest(OidcRoute::PATH, OidcRoute::router())
+        .layer(from_fn_with_state(app_state.clone(), ctx_resolver))
         .layer(map_response(logger))
         .layer(CookieManagerLayer::new())
         .with_state(app_state)
@@ -0,0 +1,251 @@
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use lib_hash::{hash_scheme::HashScheme, pepper, token::generate_token};
+use lib_routes::{
+    error::{RouteError, RouterResult},
+    nested_route::NestedRoute,
+};
+use rand::Rng;
+use serde::Deserialize;
+use tower_cookies::{
+    cookie::{time::Duration, SameSite},
+    Cookie, Cookies,
+};
+
+use super::auth_route::{set_challenge_cookie, set_session_cookie, two_factor_challenge};
+use crate::{
+    libs::env::env_or,
+    models::{
+        base,
+        oidc_model::{consume_login_state, create_login_state, link_identity, use_identity},
+        totp_model::is_totp_enabled,
+        user_model::{
+            get_email_verification, get_user_id, get_username_by_email, verify_email,
+            CreateUserModel, UserModel,
+        },
+    },
+    services::{
+        oidc::{IdTokenClaims, OidcProvider},
+        suspension::check_not_suspended,
+    },
+    AppState,
+};
+
+const OIDC_STATE_COOKIE: &str = "oidc_state";
+const LOGIN_STATE_LIFE_IN_MINUTES: i32 = 10;
+
+/// "Sign in with ..." through the OpenID Connect provider configured with OIDC_* env vars.
+pub struct OidcRoute;
+
+impl NestedRoute<AppState> for OidcRoute {
+    const PATH: &'static str = "/auth/oidc";
+    fn router() -> Router<AppState> {
+        Router::new()
+            .route("/login", get(oidc_login))
+            .route("/callback", get(oidc_callback))
+    }
+}
+
+fn provider(s: &AppState) -> RouterResult<&OidcProvider> {
+    s.oidc.as_deref().ok_or(RouteError::Validation(
+        "OIDC login is not configured".to_string(),
+    ))
+}
+
+/// Redirects to the provider, remembering the state in a cookie so the callback can only finish a login this browser started.
+async fn oidc_login(State(s): State<AppState>, cookies: Cookies) -> RouterResult<Redirect> {
+    let provider = provider(&s)?;
+
+    let state = generate_token();
+    let nonce = generate_token();
+    let code_verifier = generate_token();
+    create_login_state(
+        &s.pool,
+        &state,
+        &code_verifier,
+        &nonce,
+        LOGIN_STATE_LIFE_IN_MINUTES,
+    )
+    .await?;
+
+    let mut state_cookie = Cookie::new(OIDC_STATE_COOKIE, state.clone());
+    state_cookie.set_path(OidcRoute::PATH);
+    state_cookie.set_http_only(true);
+    // lax so the cookie is sent on the provider's top level redirect back
+    state_cookie.set_same_site(SameSite::Lax);
+    state_cookie.set_max_age(Duration::minutes(LOGIN_STATE_LIFE_IN_MINUTES as i64));
+    cookies.add(state_cookie);
+
+    Ok(Redirect::to(&provider.authorization_url(
+        &state,
+        &nonce,
+        &code_verifier,
+    )))
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Finishes the login, minting the same session as `log_in` and redirecting to the app.
+/// Users with 2FA are redirected to the 2FA step instead, with the challenge in an HttpOnly cookie.
+async fn oidc_callback(
+    State(s): State<AppState>,
+    cookies: Cookies,
+    Query(query): Query<OidcCallbackQuery>,
+) -> RouterResult<Response> {
+    let provider = provider(&s)?;
+
+    let cookie_state = cookies
+        .get(OIDC_STATE_COOKIE)
+        .map(|c| c.value().to_string());
+    cookies.remove(
+        Cookie::build(OIDC_STATE_COOKIE)
+            .path(OidcRoute::PATH)
+            .into(),
+    );
+
+    if let Some(error) = query.error {
+        println!("OIDC provider returned error: {}", error);
+        return Err(RouteError::LoginFail);
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Err(RouteError::LoginFail);
+    };
+    if cookie_state.as_deref() != Some(state.as_str()) {
+        return Err(RouteError::LoginFail);
+    }
+    let login_state = consume_login_state(&s.pool, &state)
+        .await?
+        .ok_or(RouteError::LoginFail)?;
+
+    let id_token = provider
+        .exchange_code(&code, &login_state.code_verifier)
+        .await?;
+    let claims = provider
+        .validate_id_token(&id_token, &login_state.nonce)
+        .await?;
+
+    let username = resolve_user(&s, provider.issuer(), &claims).await?;
+    check_not_suspended(&s.pool, &username).await?;
+
+    let app_url = env_or("APP_URL", "http://localhost:3000".to_string());
+    if is_totp_enabled(&s.pool, &username).await? {
+        let challenge = two_factor_challenge(username)?;
+        set_challenge_cookie(&challenge, &cookies);
+        return Ok(Redirect::to(&format!("{}/login/2fa", app_url)).into_response());
+    }
+
+    set_session_cookie(username, &cookies)?;
+    Ok(Redirect::to(&env_or("OIDC_LOGIN_REDIRECT", app_url)).into_response())
+}
+
+/// Finds the user linked to the provider identity.
+/// Otherwise links the user with the same email when both the provider and the user have verified it,
+/// or creates a new user.
+async fn resolve_user(s: &AppState, issuer: &str, claims: &IdTokenClaims) -> RouterResult<String> {
+    if let Some(username) = use_identity(&s.pool, issuer, &claims.sub).await? {
+        return Ok(username);
+    }
+
+    let email = claims
+        .email
+        .as_deref()
+        .map(str::trim)
+        .ok_or(RouteError::Validation(
+            "The identity provider did not share an email".to_string(),
+        ))?;
+
+    let username = match get_username_by_email(email, &s.pool).await? {
+        Some(username) => {
+            let local_verified = get_email_verification(&username, &s.pool)
+                .await?
+                .is_some_and(|u| u.email_verified_at.is_some());
+            // linking to an unverified email would let whoever registered it first take over the login
+            if !claims.email_verified() || !local_verified {
+                return Err(RouteError::AlreadyTaken(email.to_string()));
+            }
+            username
+        }
+        None => create_user(s, email, claims).await?,
+    };
+
+    link_identity(&s.pool, issuer, &claims.sub, &username, Some(email)).await?;
+    Ok(username)
+}
+
+const MAX_USERNAME_ATTEMPTS: usize = 10;
+
+/// Creates a user for a new provider identity, with an unusable random password.
+async fn create_user(s: &AppState, email: &str, claims: &IdTokenClaims) -> RouterResult<String> {
+    let username = available_username(s, claims, email).await?;
+
+    let pepper = pepper::current();
+    let (pwd_hash, pwd_salt) = HashScheme::CURRENT
+        .hasher()
+        .hash_peppered(&generate_token(), pepper)?;
+
+    let create_model = CreateUserModel {
+        username: username.clone(),
+        email: email.to_string(),
+        first_name: truncate(claims.given_name.as_deref().unwrap_or(&username), 32),
+        last_name: truncate(claims.family_name.as_deref().unwrap_or_default(), 32),
+        pwd_hash,
+        pwd_salt,
+        hash_scheme: HashScheme::CURRENT,
+        pepper_id: pepper.map(|p| p.id()),
+    };
+    let user_id = base::create::<UserModel, _>(create_model, &s.pool).await?;
+    s.recommender.add_user(user_id);
+
+    if claims.email_verified() {
+        verify_email(&username, email, &s.pool).await?;
+    }
+
+    Ok(username)
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    value.trim().chars().take(max_chars).collect()
+}
+
+/// Derives a username from the provider's preferred username or the email, adding digits until one is free.
+async fn available_username(
+    s: &AppState,
+    claims: &IdTokenClaims,
+    email: &str,
+) -> RouterResult<String> {
+    let source = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or(email.split('@').next().unwrap_or_default());
+    let mut base_name = source
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .take(26)
+        .collect::<String>();
+    if base_name.is_empty() {
+        base_name = "user".to_string();
+    }
+
+    let mut candidate = base_name.clone();
+    for _ in 0..MAX_USERNAME_ATTEMPTS {
+        if get_user_id(&candidate, &s.pool).await?.is_none() {
+            return Ok(candidate);
+        }
+        candidate = format!(
+            "{}{}",
+            base_name,
+            rand::thread_rng().gen_range(1000..1000000)
+        );
+    }
+    Err(RouteError::AlreadyTaken(base_name))
+}
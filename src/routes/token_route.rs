@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::NaiveDateTime;
+use ctx::{Ctx, Scope};
+use lib_routes::{
+    error::{RouteError, RouterResult},
+    nested_route::NestedRoute,
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    libs::validation::validate_struct,
+    models::access_token_model::{
+        create_access_token, list_access_tokens, revoke_access_token, CreateAccessTokenModel,
+        ReadAccessTokenModel,
+    },
+    services::access_token::generate_access_token,
+    AppState,
+};
+
+pub struct TokenRoute;
+
+impl NestedRoute<AppState> for TokenRoute {
+    const PATH: &'static str = "/tokens";
+    fn router() -> Router<AppState> {
+        Router::new()
+            .route("/", get(list_tokens).post(create_token))
+            .route("/:id", delete(revoke_token))
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateTokenModel {
+    #[validate(length(min = 1, max = 64, message = "Invalid token name length"))]
+    name: String,
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    scopes: Vec<String>,
+    /// Never expires when not set.
+    #[validate(range(min = 1, max = 3650, message = "Invalid expiry"))]
+    expires_in_days: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct CreatedTokenModel {
+    id: i64,
+    name: String,
+    /// Only ever shown here, the token cannot be recovered later.
+    token: String,
+    scopes: Vec<String>,
+    expires_at: Option<NaiveDateTime>,
+}
+
+/// Creates a named personal access token with the given scopes.
+async fn create_token(
+    ctx: Ctx,
+    State(s): State<AppState>,
+    Json(body): Json<CreateTokenModel>,
+) -> RouterResult<(StatusCode, Json<CreatedTokenModel>)> {
+    ctx.require_session()?;
+    validate_struct(&body)?;
+
+    let requested = body
+        .scopes
+        .iter()
+        .map(|s| s.trim().parse::<Scope>())
+        .collect::<RouterResult<Vec<_>>>()?;
+    let scopes = Scope::ALL
+        .into_iter()
+        .filter(|s| requested.contains(s))
+        .collect::<Vec<_>>();
+
+    let (token, token_hash) = generate_access_token();
+    let create_model = CreateAccessTokenModel {
+        username: ctx.username().to_string(),
+        name: body.name.trim().to_string(),
+        token_hash,
+        scopes: scopes.iter().map(|s| s.as_str().to_string()).collect(),
+        expires_in_days: body.expires_in_days,
+    };
+    let created = create_access_token(&s.pool, create_model).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedTokenModel {
+            id: created.id,
+            name: created.name,
+            token,
+            scopes: created.scopes,
+            expires_at: created.expires_at,
+        }),
+    ))
+}
+
+async fn list_tokens(
+    ctx: Ctx,
+    State(s): State<AppState>,
+) -> RouterResult<Json<Vec<ReadAccessTokenModel>>> {
+    ctx.require_session()?;
+    let tokens = list_access_tokens(&s.pool, ctx.username()).await?;
+    Ok(Json(tokens))
+}
+
+async fn revoke_token(
+    ctx: Ctx,
+    State(s): State<AppState>,
+    Path(id): Path<i64>,
+) -> RouterResult<()> {
+    ctx.require_session()?;
+    let rows_affected = revoke_access_token(&s.pool, ctx.username(), id).await?;
+    if rows_affected == 0 {
+        return Err(RouteError::Validation("Token not found".to_string()));
+    }
+    Ok(())
+}
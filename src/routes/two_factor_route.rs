@@ -0,0 +1,136 @@
+use axum::{
+    extract::State,
+    routing::{delete, post},
+    Json, Router,
+};
+use ctx::Ctx;
+use lib_routes::{
+    error::{RouteError, RouterResult},
+    nested_route::NestedRoute,
+};
+use lib_totp::Totp;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    libs::{env::env_or, validation::validate_struct},
+    models::{
+        recovery_code_model::{delete_recovery_codes, replace_recovery_codes},
+        totp_model::{delete_totp, enable_totp, get_totp, upsert_pending_totp},
+    },
+    services::two_factor::{
+        check_totp_code, generate_recovery_codes, verify_recovery_code, verify_totp_code,
+    },
+    AppState,
+};
+
+pub struct TwoFactorRoute;
+
+impl NestedRoute<AppState> for TwoFactorRoute {
+    const PATH: &'static str = "/2fa";
+    fn router() -> Router<AppState> {
+        Router::new()
+            .route("/enroll", post(enroll))
+            .route("/confirm", post(confirm))
+            .route("/", delete(disable))
+    }
+}
+
+#[derive(Serialize)]
+pub struct EnrollModel {
+    secret: String,
+    provisioning_uri: String,
+}
+
+/// Starts enrollment with a new secret. 2FA is not enabled until a code is confirmed.
+async fn enroll(ctx: Ctx, State(s): State<AppState>) -> RouterResult<Json<EnrollModel>> {
+    ctx.require_session()?;
+    let username = ctx.username();
+    if let Some(totp) = get_totp(&s.pool, username).await? {
+        if totp.enabled_at.is_some() {
+            return Err(RouteError::Validation("2FA is already enabled".to_string()));
+        }
+    }
+
+    let totp = Totp::generate();
+    let secret = totp.secret_base32();
+    upsert_pending_totp(&s.pool, username, &secret).await?;
+
+    let issuer = env_or("TOTP_ISSUER", "Flex Forum".to_string());
+    Ok(Json(EnrollModel {
+        provisioning_uri: totp.provisioning_uri(&issuer, username),
+        secret,
+    }))
+}
+
+#[derive(Deserialize, Validate)]
+pub struct TwoFactorCodeModel {
+    #[validate(length(min = 6, max = 6, message = "Invalid code length"))]
+    code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesModel {
+    recovery_codes: Vec<String>,
+}
+
+/// Enables 2FA with the first code from the authenticator, returning the recovery codes.
+/// The recovery codes are only ever shown here.
+async fn confirm(
+    ctx: Ctx,
+    State(s): State<AppState>,
+    Json(body): Json<TwoFactorCodeModel>,
+) -> RouterResult<Json<RecoveryCodesModel>> {
+    ctx.require_session()?;
+    validate_struct(&body)?;
+    let username = ctx.username();
+
+    let totp = get_totp(&s.pool, username)
+        .await?
+        .ok_or(RouteError::Validation(
+            "2FA enrollment not started".to_string(),
+        ))?;
+    if totp.enabled_at.is_some() {
+        return Err(RouteError::Validation("2FA is already enabled".to_string()));
+    }
+
+    let step = check_totp_code(&totp.secret, &body.code)?
+        .ok_or(RouteError::Validation("Invalid 2FA code".to_string()))?;
+
+    let (recovery_codes, models) = generate_recovery_codes(username)?;
+    let mut transaction = s.pool.begin().await?;
+    replace_recovery_codes(&mut transaction, username, models).await?;
+    enable_totp(&mut transaction, username, step as i64).await?;
+    transaction.commit().await?;
+
+    Ok(Json(RecoveryCodesModel { recovery_codes }))
+}
+
+#[derive(Deserialize)]
+pub struct DisableTwoFactorModel {
+    code: Option<String>,
+    recovery_code: Option<String>,
+}
+
+/// Disables 2FA, requiring a current code or an unused recovery code.
+async fn disable(
+    ctx: Ctx,
+    State(s): State<AppState>,
+    Json(body): Json<DisableTwoFactorModel>,
+) -> RouterResult<()> {
+    ctx.require_session()?;
+    let username = ctx.username();
+
+    let verified = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => verify_totp_code(&s.pool, username, code).await?,
+        (None, Some(code)) => verify_recovery_code(&s.pool, username, code).await?,
+        (None, None) => false,
+    };
+    if !verified {
+        return Err(RouteError::Validation("Invalid 2FA code".to_string()));
+    }
+
+    delete_totp(&s.pool, username).await?;
+    delete_recovery_codes(&s.pool, username).await?;
+    Ok(())
+}
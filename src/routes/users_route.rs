@@ -1,17 +1,28 @@
+use crate::libs::validation::validate_struct;
 use crate::middleware::auth_mw::AUTH_TOKEN;
+use crate::middleware::scope_mw::require_scope;
+use crate::models::access_token_model::revoke_all_access_tokens;
 use crate::models::base;
+use crate::models::content_model::get_post_ids_by_username;
 use crate::models::following_model::FollowingModel;
+use crate::models::password_reset_model::use_reset_tokens;
+use crate::models::profile_visit_model::record_profile_visit;
 use crate::models::user_model;
 use crate::models::user_model::UserModel;
+use crate::routes::auth_route::set_session_cookie;
+use crate::services::email_verification::{require_verified, send_verification_email, GatedAction};
+use crate::services::password::{get_hash_model, set_password, verify_password};
 use crate::AppState;
 use axum::extract::Path;
+use axum::middleware::from_fn;
 use axum::routing::delete;
 use axum::routing::get;
 use axum::routing::post;
+use axum::routing::put;
 use axum::Router;
 use axum::{extract::State, Json};
-use ctx::Ctx;
-use lib_routes::error::RouterResult;
+use ctx::{AuthUser, Ctx, InteractionsWriteScope, ProfileReadScope};
+use lib_routes::error::{RouteError, RouterResult};
 use lib_routes::nested_route::NestedRoute;
 use serde::Deserialize;
 use serde::Serialize;
@@ -19,18 +30,29 @@ use sqlb::Fields;
 use sqlx::prelude::FromRow;
 use tower_cookies::Cookie;
 use tower_cookies::Cookies;
+use validator::Validate;
 
 pub struct UserRoute;
 
 impl NestedRoute<AppState> for UserRoute {
     const PATH: &'static str = "/users";
     fn router() -> Router<AppState> {
+        let profile_read = || from_fn(require_scope::<ProfileReadScope>);
         Router::new()
-            .route("/:username", get(get_user))
-            .route("/list/:username", get(list_users))
+            .route("/:username", get(get_user).route_layer(profile_read()))
+            .route(
+                "/list/:username",
+                get(list_users).route_layer(profile_read()),
+            )
             .route("/delete", delete(delete_user))
-            .route("/follow/:following", post(follow_user))
-            .route("/follow/:following", delete(unfollow_user))
+            .route("/password", put(change_password))
+            .route("/email/resend", post(resend_verification_email))
+            .route(
+                "/follow/:following",
+                post(follow_user)
+                    .delete(unfollow_user)
+                    .route_layer(from_fn(require_scope::<InteractionsWriteScope>)),
+            )
     }
 }
 
@@ -42,17 +64,23 @@ pub struct ReadUserModel {
 }
 
 pub async fn get_user(
-    _ctx: Ctx,
+    ctx: Ctx,
     Path(username): Path<String>,
     State(s): State<AppState>,
 ) -> RouterResult<Json<Option<ReadUserModel>>> {
     let read_user =
         super::models::base::get_one::<UserModel, _, _>("username", &username, &s.pool).await?;
+    if read_user.is_some() && username != ctx.username() {
+        // only a signal for the recommender, the profile is returned either way
+        if let Err(e) = record_profile_visit(&s.pool, ctx.username(), &username).await {
+            println!("Could not record profile visit: {:?}", e);
+        }
+    }
     Ok(Json(read_user))
 }
 
 pub async fn list_users(
-    _ctx: Ctx,
+    _: Ctx,
     Path(username): Path<String>,
     State(s): State<AppState>,
 ) -> RouterResult<Json<Vec<ReadUserModel>>> {
@@ -67,12 +95,19 @@ pub async fn list_users(
 }
 
 pub async fn delete_user(
-    ctx: Ctx,
+    AuthUser { id: user_id, ctx }: AuthUser,
     cookies: Cookies,
     State(s): State<AppState>,
 ) -> RouterResult<()> {
-    base::delete::<UserModel, &str>("username", ctx.jwt().username(), &s.pool).await?;
+    ctx.require_session()?;
+    // their posts are deleted with them
+    let post_ids = get_post_ids_by_username(&s.pool, ctx.username()).await?;
+    base::delete::<UserModel, &str>("username", ctx.username(), &s.pool).await?;
     cookies.remove(Cookie::from(AUTH_TOKEN));
+    s.recommender.remove_user(user_id);
+    for post_id in post_ids {
+        s.recommender.remove_post(post_id);
+    }
 
     Ok(())
 }
@@ -84,30 +119,81 @@ pub struct FollowingCreateModel {
 }
 
 async fn follow_user(
-    ctx: Ctx,
+    AuthUser { id: user_id, ctx }: AuthUser,
     State(s): State<AppState>,
     Path(following): Path<String>,
 ) -> RouterResult<()> {
+    require_verified(&s.pool, ctx.username(), GatedAction::Follow).await?;
     let follow = FollowingCreateModel {
-        follower: ctx.jwt().username().to_string(),
-        following,
+        follower: ctx.username().to_string(),
+        following: following.clone(),
     };
     super::models::base::create::<FollowingModel, FollowingCreateModel>(follow, &s.pool).await?;
+    let post_ids = get_post_ids_by_username(&s.pool, &following).await?;
+    s.recommender.follow(user_id, post_ids, true);
     Ok(())
 }
 
 async fn unfollow_user(
-    ctx: Ctx,
+    AuthUser { id: user_id, ctx }: AuthUser,
     State(s): State<AppState>,
     Path(following): Path<String>,
 ) -> RouterResult<()> {
     super::models::base::delete_with_both::<FollowingModel, _, _>(
         "follower",
-        ctx.jwt().username(),
+        ctx.username(),
         "following",
-        following,
+        &following,
         &s.pool,
     )
     .await?;
+    let post_ids = get_post_ids_by_username(&s.pool, &following).await?;
+    s.recommender.follow(user_id, post_ids, false);
     Ok(())
 }
+
+#[derive(Deserialize, Validate)]
+pub struct ChangePasswordModel {
+    #[validate(length(min = 1, max = 64, message = "Invalid password length"))]
+    current_password: String,
+    #[validate(length(min = 1, max = 64, message = "Invalid password length"))]
+    new_password: String,
+}
+
+/// Changes the password of the logged in user, requiring their current password.
+/// Signs out every other session, this one gets a new auth cookie.
+async fn change_password(
+    ctx: Ctx,
+    State(s): State<AppState>,
+    cookies: Cookies,
+    Json(body): Json<ChangePasswordModel>,
+) -> RouterResult<()> {
+    ctx.require_session()?;
+    validate_struct(&body)?;
+    let username = ctx.username();
+
+    let hash_model = get_hash_model(&s.pool, username)
+        .await?
+        .ok_or(RouteError::InvalidAuth)?;
+    verify_password(&s.pool, &hash_model, body.current_password.trim()).await?;
+
+    set_password(&s.pool, username, body.new_password.trim()).await?;
+    use_reset_tokens(&s.pool, username).await?;
+    revoke_all_access_tokens(&s.pool, username).await?;
+    user_model::invalidate_sessions(username, &s.pool).await?;
+    set_session_cookie(username.to_string(), &cookies)?;
+
+    Ok(())
+}
+
+/// Sends the logged in user another verification email, rate limited per user.
+async fn resend_verification_email(ctx: Ctx, State(s): State<AppState>) -> RouterResult<()> {
+    ctx.require_session()?;
+    let user = user_model::get_email_verification(ctx.username(), &s.pool)
+        .await?
+        .ok_or(RouteError::InvalidAuth)?;
+    if user.email_verified_at.is_some() {
+        return Err(RouteError::Validation("Email already verified".to_string()));
+    }
+    send_verification_email(&s.pool, s.mailer.as_ref(), &user.username, &user.email).await
+}
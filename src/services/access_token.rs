@@ -0,0 +1,34 @@
+use ctx::{Ctx, Scope};
+use lib_hash::token::{generate_token, token_digest};
+use lib_routes::error::RouterResult;
+use sqlx::PgPool;
+
+use crate::models::access_token_model::use_access_token;
+
+/// Marks bearer tokens as access tokens rather than session jwts, and makes leaked tokens easy to search for.
+pub const ACCESS_TOKEN_PREFIX: &str = "ffpat_";
+
+/// Generates a new access token, returning the plain token to show the user once and its digest to store.
+pub fn generate_access_token() -> (String, String) {
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, generate_token());
+    let digest = token_digest(&token);
+    (token, digest)
+}
+
+/// Resolves an access token into a Ctx carrying its scopes, None if unknown, revoked or expired.
+pub async fn resolve_access_token(pool: &PgPool, token: &str) -> RouterResult<Option<Ctx>> {
+    let Some(access_token) = use_access_token(pool, &token_digest(token)).await? else {
+        return Ok(None);
+    };
+    // scopes are validated when the token is created, unknown ones are dropped in case a scope is retired
+    let scopes = access_token
+        .scopes
+        .iter()
+        .filter_map(|s| s.parse::<Scope>().ok())
+        .collect();
+    Ok(Some(Ctx::from_access_token(
+        access_token.username,
+        access_token.id,
+        scopes,
+    )))
+}
@@ -0,0 +1,59 @@
+use crate::{libs::env::env_or, models::content_model::PostType};
+
+/// What is known about a post the recommender cannot score, from the database instead of the model.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ColdStartSignals {
+    pub num_likes: i64,
+    /// Whether the user follows the author of the post.
+    pub is_following: bool,
+}
+
+/// Weights of the fallback score for users and posts the model does not know yet.
+/// The defaults keep it in the range of predictions for the 0 to 1 like target,
+/// so that unknown posts can still outrank known ones.
+#[derive(Debug, Clone, Copy)]
+pub struct ColdStartConfig {
+    /// Per log of the number of likes plus one.
+    pub like_weight: f32,
+    pub follow_weight: f32,
+    pub images_weight: f32,
+    pub workout_weight: f32,
+}
+
+impl Default for ColdStartConfig {
+    fn default() -> Self {
+        Self {
+            like_weight: 0.1,
+            follow_weight: 0.4,
+            images_weight: 0.1,
+            workout_weight: 0.05,
+        }
+    }
+}
+
+impl ColdStartConfig {
+    /// COLD_START_LIKE_WEIGHT, COLD_START_FOLLOW_WEIGHT, COLD_START_IMAGES_WEIGHT and COLD_START_WORKOUT_WEIGHT.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            like_weight: env_or("COLD_START_LIKE_WEIGHT", default.like_weight),
+            follow_weight: env_or("COLD_START_FOLLOW_WEIGHT", default.follow_weight),
+            images_weight: env_or("COLD_START_IMAGES_WEIGHT", default.images_weight),
+            workout_weight: env_or("COLD_START_WORKOUT_WEIGHT", default.workout_weight),
+        }
+    }
+
+    pub fn score(&self, post_type: &PostType, signals: ColdStartSignals) -> f32 {
+        let popularity = self.like_weight * (signals.num_likes.max(0) as f32).ln_1p();
+        let following = if signals.is_following {
+            self.follow_weight
+        } else {
+            0.0
+        };
+        let post_type = match post_type {
+            PostType::Images => self.images_weight,
+            PostType::Workout => self.workout_weight,
+        };
+        popularity + following + post_type
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::HashSet, env, str::FromStr};
+
+use chrono::{TimeDelta, Utc};
+use lib_hash::signature;
+use lib_routes::error::{RouteError, RouterResult};
+use once_cell::sync::Lazy;
+use sqlx::PgPool;
+
+use crate::{
+    libs::env::env_or,
+    models::user_model::{
+        get_email_verification, is_email_verified, try_set_verification_sent,
+        verification_retry_after, verify_email,
+    },
+    services::mailer::{Email, Mailer},
+};
+
+/// Signs verification links, kept separate from JWT_SECRET so that a link can never be used as a session.
+static EMAIL_VERIFICATION_SECRET: Lazy<String> = Lazy::new(|| {
+    env::var("EMAIL_VERIFICATION_SECRET").expect("Could not get EMAIL_VERIFICATION_SECRET")
+});
+
+/// Actions unverified users are blocked from, from a comma separated UNVERIFIED_BLOCKED_ACTIONS.
+/// e.g. UNVERIFIED_BLOCKED_ACTIONS="post,follow,like", an empty value blocks nothing.
+static BLOCKED_ACTIONS: Lazy<HashSet<GatedAction>> = Lazy::new(|| {
+    env_or("UNVERIFIED_BLOCKED_ACTIONS", "post,follow".to_string())
+        .split(',')
+        .filter(|a| !a.trim().is_empty())
+        .map(|a| {
+            a.parse()
+                .unwrap_or_else(|_| panic!("Invalid UNVERIFIED_BLOCKED_ACTIONS entry: {}", a))
+        })
+        .collect()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GatedAction {
+    Post,
+    Follow,
+    Like,
+}
+
+impl FromStr for GatedAction {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "post" => Ok(GatedAction::Post),
+            "follow" => Ok(GatedAction::Follow),
+            "like" => Ok(GatedAction::Like),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Errs with EmailNotVerified if the action is blocked for unverified users and the user is unverified.
+pub async fn require_verified(
+    pool: &PgPool,
+    username: &str,
+    action: GatedAction,
+) -> RouterResult<()> {
+    if !BLOCKED_ACTIONS.contains(&action) {
+        return Ok(());
+    }
+    if !is_email_verified(username, pool).await? {
+        return Err(RouteError::EmailNotVerified);
+    }
+    Ok(())
+}
+
+/// The email is part of the signed message so that changing it invalidates links sent to the old address.
+fn link_message(username: &str, email: &str, expires: i64) -> String {
+    format!("{}\n{}\n{}", username, email, expires)
+}
+
+/// Emails a signed, expiring verification link to the user.
+/// Errs with RateLimited if one was sent too recently, see EMAIL_VERIFICATION_RESEND_SECONDS.
+pub async fn send_verification_email(
+    pool: &PgPool,
+    mailer: &dyn Mailer,
+    username: &str,
+    email: &str,
+) -> RouterResult<()> {
+    let resend_secs = env_or("EMAIL_VERIFICATION_RESEND_SECONDS", 60);
+    if !try_set_verification_sent(username, resend_secs, pool).await? {
+        let retry_after = verification_retry_after(username, resend_secs, pool).await?;
+        return Err(RouteError::RateLimited(retry_after));
+    }
+
+    let life_in_hours = env_or("EMAIL_VERIFICATION_LIFE_IN_HOURS", 24);
+    let expires = (Utc::now() + TimeDelta::hours(life_in_hours)).timestamp();
+    let signature = signature::sign(
+        EMAIL_VERIFICATION_SECRET.as_bytes(),
+        &link_message(username, email, expires),
+    )?;
+
+    let api_url = env_or("API_URL", "http://localhost:3001".to_string());
+    let email = Email {
+        to: email.to_string(),
+        subject: "Verify your Flex Forum email".to_string(),
+        body: format!(
+            "Hi {},\n\nUse the link below to verify your email. It expires in {} hours.\n\n{}/users/email/verify?username={}&expires={}&signature={}\n\nIf you did not create a Flex Forum account you can ignore this email.",
+            username, life_in_hours, api_url, username, expires, signature
+        ),
+    };
+    mailer.send(email).await?;
+
+    Ok(())
+}
+
+/// Checks a verification link and marks the user's email verified.
+/// Returns false if the link is expired, tampered with, or was sent to a different email.
+pub async fn confirm_verification_link(
+    pool: &PgPool,
+    username: &str,
+    expires: i64,
+    signature: &str,
+) -> RouterResult<bool> {
+    if expires < Utc::now().timestamp() {
+        return Ok(false);
+    }
+    let Some(user) = get_email_verification(username, pool).await? else {
+        return Ok(false);
+    };
+    let message = link_message(&user.username, &user.email, expires);
+    if signature::verify(EMAIL_VERIFICATION_SECRET.as_bytes(), &message, signature).is_err() {
+        return Ok(false);
+    }
+    // checked after the signature so that unsigned links do not reveal whether a user is verified
+    if user.email_verified_at.is_some() {
+        return Ok(true);
+    }
+    verify_email(&user.username, &user.email, pool).await?;
+    Ok(true)
+}
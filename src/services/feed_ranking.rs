@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use chrono::{TimeDelta, Utc};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{libs::env::env_or, models::content_model::ContentModel};
+
+/// Re-ranking of the scored posts of a feed page, so that a few authors or one post type
+/// do not fill every page and new posts get seen before they have interactions.
+#[derive(Debug, Clone, Copy)]
+pub struct FeedRankingConfig {
+    /// Most posts of one author per page, 0 for no limit.
+    pub max_per_author: usize,
+    /// Most posts of the same type in a row, 0 for no limit.
+    pub max_type_run: usize,
+    /// The chance that the last slot of a page goes to a random fresh post instead of the next best one.
+    pub explore_epsilon: f64,
+    /// How new a post has to be to be explored.
+    pub fresh_hours: i64,
+}
+
+impl Default for FeedRankingConfig {
+    fn default() -> Self {
+        Self {
+            max_per_author: 1,
+            max_type_run: 2,
+            explore_epsilon: 0.1,
+            fresh_hours: 24,
+        }
+    }
+}
+
+impl FeedRankingConfig {
+    /// FEED_MAX_PER_AUTHOR, FEED_MAX_TYPE_RUN, FEED_EXPLORE_EPSILON and FEED_FRESH_HOURS.
+    /// Panics if FEED_EXPLORE_EPSILON is not a number from 0 to 1.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let explore_epsilon = env_or("FEED_EXPLORE_EPSILON", default.explore_epsilon);
+        if !(0.0..=1.0).contains(&explore_epsilon) {
+            panic!("Invalid FEED_EXPLORE_EPSILON: {}", explore_epsilon);
+        }
+        Self {
+            max_per_author: env_or("FEED_MAX_PER_AUTHOR", default.max_per_author),
+            max_type_run: env_or("FEED_MAX_TYPE_RUN", default.max_type_run),
+            explore_epsilon,
+            fresh_hours: env_or("FEED_FRESH_HOURS", default.fresh_hours),
+        }
+    }
+
+    /// Picks a page of `num_taken` posts from `scored`, best first within the author and post type limits.
+    pub fn rerank(
+        &self,
+        mut scored: Vec<(ContentModel, f32)>,
+        num_taken: usize,
+    ) -> Vec<ContentModel> {
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let explored = self.explore(&mut scored, num_taken);
+        let mut per_author = HashMap::<String, usize>::new();
+        if let Some(post) = &explored {
+            *per_author.entry(post.username.clone()).or_default() += 1;
+        }
+
+        let slots = num_taken - usize::from(explored.is_some());
+        let mut page = Vec::with_capacity(num_taken);
+        while page.len() < slots && !scored.is_empty() {
+            let (post, _) = scored.remove(self.next(&scored, &page, &per_author));
+            *per_author.entry(post.username.clone()).or_default() += 1;
+            page.push(post);
+        }
+        page.extend(explored);
+        page
+    }
+
+    /// With a chance of `explore_epsilon`, takes a random fresh post out of `scored`.
+    fn explore(
+        &self,
+        scored: &mut Vec<(ContentModel, f32)>,
+        num_taken: usize,
+    ) -> Option<ContentModel> {
+        let mut rng = rand::thread_rng();
+        if num_taken == 0 || !rng.gen_bool(self.explore_epsilon.clamp(0.0, 1.0)) {
+            return None;
+        }
+        let fresh_after = Utc::now().naive_utc() - TimeDelta::hours(self.fresh_hours);
+        let fresh = scored
+            .iter()
+            .enumerate()
+            .filter(|(_, (post, _))| post.created_at >= fresh_after)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let &index = fresh.choose(&mut rng)?;
+        Some(scored.remove(index).0)
+    }
+
+    /// The index of the best post that keeps both limits. When none does the post type limit
+    /// is dropped and then the author limit, so that a page is only short when `scored` runs out.
+    fn next(
+        &self,
+        scored: &[(ContentModel, f32)],
+        page: &[ContentModel],
+        per_author: &HashMap<String, usize>,
+    ) -> usize {
+        let within_author = |post: &ContentModel| {
+            self.max_per_author == 0
+                || per_author.get(&post.username).copied().unwrap_or_default() < self.max_per_author
+        };
+        let within_type = |post: &ContentModel| {
+            self.max_type_run == 0
+                || page.len() < self.max_type_run
+                || page[page.len() - self.max_type_run..]
+                    .iter()
+                    .any(|previous| previous.post_type != post.post_type)
+        };
+        scored
+            .iter()
+            .position(|(post, _)| within_author(post) && within_type(post))
+            .or_else(|| scored.iter().position(|(post, _)| within_author(post)))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::models::content_model::PostType;
+
+    fn post(
+        id: i64,
+        username: &str,
+        post_type: PostType,
+        created_at: NaiveDateTime,
+    ) -> ContentModel {
+        ContentModel {
+            id,
+            username: username.to_string(),
+            num_images: 0,
+            post_type,
+            description: None,
+            created_at,
+            deactivated_at: None,
+        }
+    }
+
+    /// Posts with descending scores, created long before they could be explored.
+    fn scored(posts: &[(&str, PostType)]) -> Vec<(ContentModel, f32)> {
+        let old = Utc::now().naive_utc() - TimeDelta::days(30);
+        posts
+            .iter()
+            .enumerate()
+            .map(|(i, (username, post_type))| {
+                (
+                    post(i as i64, username, post_type.clone(), old),
+                    1.0 - i as f32 / 10.0,
+                )
+            })
+            .collect()
+    }
+
+    fn ids(page: &[ContentModel]) -> Vec<i64> {
+        page.iter().map(|p| p.id).collect()
+    }
+
+    fn config(max_per_author: usize, max_type_run: usize) -> FeedRankingConfig {
+        FeedRankingConfig {
+            max_per_author,
+            max_type_run,
+            explore_epsilon: 0.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn limits_posts_per_author() {
+        let posts = scored(&[
+            ("a", PostType::Images),
+            ("a", PostType::Workout),
+            ("b", PostType::Images),
+        ]);
+        let page = config(1, 0).rerank(posts, 2);
+        assert_eq!(ids(&page), vec![0, 2]);
+    }
+
+    #[test]
+    fn limits_runs_of_a_post_type() {
+        let posts = scored(&[
+            ("a", PostType::Images),
+            ("b", PostType::Images),
+            ("c", PostType::Images),
+            ("d", PostType::Workout),
+        ]);
+        let page = config(0, 2).rerank(posts, 4);
+        assert_eq!(ids(&page), vec![0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn fills_short_pages_past_the_limits() {
+        let posts = scored(&[
+            ("a", PostType::Images),
+            ("a", PostType::Images),
+            ("a", PostType::Images),
+        ]);
+        let page = config(1, 1).rerank(posts, 3);
+        assert_eq!(ids(&page), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn explores_a_fresh_post_in_the_last_slot() {
+        let mut posts = scored(&[("a", PostType::Images), ("b", PostType::Workout)]);
+        posts.push((post(9, "c", PostType::Images, Utc::now().naive_utc()), 0.0));
+        let config = FeedRankingConfig {
+            explore_epsilon: 1.0,
+            ..config(0, 0)
+        };
+        assert_eq!(ids(&config.rerank(posts, 2)), vec![0, 9]);
+    }
+}
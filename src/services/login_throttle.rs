@@ -0,0 +1,111 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::http::HeaderMap;
+use lib_hash::token::token_digest;
+use lib_routes::error::{RouteError, RouterResult};
+use once_cell::sync::Lazy;
+use sqlx::PgPool;
+
+use crate::{
+    libs::env::env_or,
+    models::login_attempt_model::{clear, lock, locked_for, record_failure},
+};
+
+static LIMITS: Lazy<LoginLimits> = Lazy::new(LoginLimits::from_env);
+
+/// Failed logins allowed before lockouts start, with lockouts doubling on each further failure.
+#[derive(Debug)]
+struct LoginLimits {
+    free_attempts_per_user: i32,
+    free_attempts_per_ip: i32,
+    base_lockout_secs: i64,
+    max_lockout_secs: i64,
+    /// Failures older than this are forgotten.
+    window_secs: i64,
+    /// Number of proxies in front of the server that append to X-Forwarded-For, 0 to ignore the header.
+    /// Each one appends the address it received from, so the client is this many entries from the right.
+    trusted_proxies: usize,
+}
+
+impl LoginLimits {
+    fn from_env() -> Self {
+        Self {
+            free_attempts_per_user: env_or("LOGIN_FREE_ATTEMPTS_PER_USER", 5),
+            free_attempts_per_ip: env_or("LOGIN_FREE_ATTEMPTS_PER_IP", 20),
+            base_lockout_secs: env_or("LOGIN_LOCKOUT_BASE_SECONDS", 30),
+            max_lockout_secs: env_or("LOGIN_LOCKOUT_MAX_SECONDS", 3600),
+            window_secs: env_or("LOGIN_ATTEMPT_WINDOW_SECONDS", 3600),
+            trusted_proxies: env_or("TRUSTED_PROXIES", 0),
+        }
+    }
+
+    /// Lockout after the given number of failures, 0 while still within the free attempts.
+    fn lockout_secs(&self, failures: i32, free_attempts: i32) -> i64 {
+        if failures < free_attempts {
+            return 0;
+        }
+        let doublings = (failures - free_attempts).min(32) as u32;
+        self.base_lockout_secs
+            .saturating_mul(2i64.saturating_pow(doublings))
+            .min(self.max_lockout_secs)
+    }
+}
+
+/// The address login attempts are tracked under.
+/// Entries left of the one added by the outermost trusted proxy are ignored, the client can set them to anything.
+pub fn client_ip(addr: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    if LIMITS.trusted_proxies > 0 {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').nth(LIMITS.trusted_proxies - 1))
+            .and_then(|ip| ip.trim().parse().ok());
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+    addr.ip()
+}
+
+/// Keyed on a digest so that any identifier fits the key column, whether or not it is a username.
+fn user_key(username: &str) -> String {
+    format!("user:{}", token_digest(username))
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+/// Errs with LoginLocked if the username or ip is locked out.
+/// Called before the password is checked so that locked out guesses cost no hashing.
+pub async fn check_login_allowed(pool: &PgPool, username: &str, ip: IpAddr) -> RouterResult<()> {
+    let secs = locked_for(pool, &[user_key(username), ip_key(ip)]).await?;
+    if secs > 0 {
+        return Err(RouteError::LoginLocked(secs));
+    }
+    Ok(())
+}
+
+/// Counts a failed login against the username and ip, locking either out once past its free attempts.
+/// Usernames are counted whether or not they exist.
+pub async fn record_login_failure(pool: &PgPool, username: &str, ip: IpAddr) -> RouterResult<()> {
+    let keys = [
+        (user_key(username), LIMITS.free_attempts_per_user),
+        (ip_key(ip), LIMITS.free_attempts_per_ip),
+    ];
+    for (key, free_attempts) in keys {
+        let failures = record_failure(pool, &key, LIMITS.window_secs).await?;
+        let secs = LIMITS.lockout_secs(failures, free_attempts);
+        if secs > 0 {
+            lock(pool, &key, secs).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Forgets the username's failures after a successful login.
+/// The ip's failures are kept so that one known password cannot reset guessing at other accounts.
+pub async fn clear_login_failures(pool: &PgPool, username: &str) -> RouterResult<()> {
+    clear(pool, &user_key(username)).await?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::{env, fmt::Debug, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use lib_routes::error::RouteError;
+use tokio::io::AsyncWriteExt;
+
+use crate::libs::env::env_or;
+
+pub type MailerResult<T> = Result<T, MailerError>;
+
+#[derive(Debug)]
+pub enum MailerError {
+    InvalidAddress(String),
+    Smtp(String),
+    IO(String),
+}
+
+impl From<MailerError> for RouteError {
+    fn from(value: MailerError) -> Self {
+        RouteError::MailerError(format!("{:?}", value))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Debug + Send + Sync {
+    async fn send(&self, email: Email) -> MailerResult<()>;
+}
+
+/// Sends mail through an SMTP relay using STARTTLS.
+#[derive(Debug)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> MailerResult<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+            .map_err(|e| MailerError::Smtp(e.to_string()))?
+            .port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        let from = from
+            .parse()
+            .map_err(|_| MailerError::InvalidAddress(from.to_string()))?;
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> MailerResult<()> {
+        let to = email
+            .to
+            .parse::<Mailbox>()
+            .map_err(|_| MailerError::InvalidAddress(email.to.clone()))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| MailerError::Smtp(e.to_string()))?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| MailerError::Smtp(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes mail to a file, or stdout if no path is given, instead of sending it.
+/// For local development and tests.
+#[derive(Debug)]
+pub struct LogMailer {
+    path: Option<PathBuf>,
+}
+
+impl LogMailer {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> MailerResult<()> {
+        let entry = format!(
+            "To: {}\nSubject: {}\n\n{}\n---\n",
+            email.to, email.subject, email.body
+        );
+        match &self.path {
+            Some(path) => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(|e| MailerError::IO(e.to_string()))?;
+                file.write_all(entry.as_bytes())
+                    .await
+                    .map_err(|e| MailerError::IO(e.to_string()))?;
+            }
+            None => println!("{}", entry),
+        }
+        Ok(())
+    }
+}
+
+/// Creates the mailer selected by MAILER, either `smtp` or `log` (the default).
+pub fn create_mailer() -> Arc<dyn Mailer> {
+    match env_or("MAILER", "log".to_string()).as_str() {
+        "smtp" => {
+            let host = env::var("SMTP_HOST").expect("SMTP_HOST not found in .env");
+            let port = env_or("SMTP_PORT", 587);
+            let credentials = env::var("SMTP_USERNAME")
+                .ok()
+                .map(|u| (u, env::var("SMTP_PASSWORD").unwrap_or_default()));
+            let from = env::var("MAIL_FROM").expect("MAIL_FROM not found in .env");
+            let mailer = SmtpMailer::new(&host, port, credentials, &from)
+                .expect("Could not create smtp mailer");
+            Arc::new(mailer)
+        }
+        "log" => Arc::new(LogMailer::new(
+            env::var("MAIL_LOG_PATH").ok().map(PathBuf::from),
+        )),
+        other => panic!("Unknown MAILER: {}", other),
+    }
+}
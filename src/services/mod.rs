@@ -1,2 +1,17 @@
+pub mod access_token;
+pub mod cold_start;
+pub mod email_verification;
+pub mod feed_ranking;
+pub mod login_throttle;
+pub mod mailer;
 pub mod ndarray;
+pub mod observation_channel;
+pub mod oidc;
+pub mod password;
+pub mod recommender;
+pub mod recommender_eval;
+pub mod recommender_snapshot;
+pub mod recommenders;
 pub mod s3;
+pub mod suspension;
+pub mod two_factor;
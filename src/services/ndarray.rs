@@ -1,172 +1,512 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use itertools::Itertools;
-use ndarray::{Array1, Array2, Array3, ArrayBase, Axis, Dim, OwnedRepr, ShapeError};
+use chrono::{DateTime, Utc};
+use ndarray::{Array1, Array2, ArrayBase, Dim, OwnedRepr, ShapeError};
 use ndarray_rand::RandomExt;
-use rand::distributions::Uniform;
+use rand::{distributions::Uniform, Rng};
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
 
-use crate::models::interactions_matrix_model::build_model;
+use lib_models::error::ModelResult;
 
-#[derive(Debug)]
+use crate::services::{
+    observation_channel::ChannelKind,
+    recommenders::{InteractionData, Recommender, RecommenderConfig, SharedRows},
+};
+
+/// Learned embeddings and the ids they belong to, saved so that restarts do not retrain from scratch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecommenderSnapshot {
+    pub version: u32,
+    /// When the embeddings last had a full training, incremental updates keep this time.
+    pub trained_at: DateTime<Utc>,
+    pub config: RecommenderConfig,
+    pub user_embeddings: Array2<f32>,
+    pub post_embeddings: Array2<f32>,
+    pub user_index_hashmap: HashMap<i64, usize>,
+    pub post_index_hashmap: HashMap<i64, usize>,
+}
+
+impl RecommenderSnapshot {
+    /// Bumped whenever the snapshot format or the meaning of the embeddings changes.
+    pub const VERSION: u32 = 4;
+}
+
+/// Interaction values of the user and post index pairs that are trained on,
+/// the observed pairs plus a sample of unobserved pairs stored as negatives (all 0).
+/// Pairs that are not stored are never trained on.
+#[derive(Debug, Clone, Default)]
+struct SparseInteractions {
+    /// values by post index, for each user index
+    user_values: SharedRows<HashMap<usize, Vec<f32>>>,
+    /// user indexes paired with each post index
+    post_users: SharedRows<Vec<usize>>,
+}
+
+impl SparseInteractions {
+    fn new(n_users: usize, n_posts: usize) -> Self {
+        Self {
+            user_values: SharedRows::new(vec![HashMap::new(); n_users]),
+            post_users: SharedRows::new(vec![Vec::new(); n_posts]),
+        }
+    }
+
+    fn insert(&mut self, u_idx: usize, p_idx: usize, values: Vec<f32>) {
+        if !self.user_values[u_idx].contains_key(&p_idx) {
+            self.user_values.get_mut(u_idx).insert(p_idx, values);
+            self.post_users.get_mut(p_idx).push(u_idx);
+        }
+    }
+
+    /// Sets one observation of a pair, storing the pair with its other observations at 0 if it is new.
+    fn set(
+        &mut self,
+        u_idx: usize,
+        p_idx: usize,
+        observation: usize,
+        value: f32,
+        n_observations: usize,
+    ) {
+        self.insert(u_idx, p_idx, vec![0.0; n_observations]);
+        if let Some(values) = self.user_values.get_mut(u_idx).get_mut(&p_idx) {
+            values[observation] = value;
+        }
+    }
+
+    fn get(&self, u_idx: usize, p_idx: usize) -> Option<&[f32]> {
+        self.user_values[u_idx].get(&p_idx).map(|v| v.as_slice())
+    }
+
+    /// post indexes paired with the user index
+    fn user_posts(&self, u_idx: usize) -> impl Iterator<Item = usize> + '_ {
+        self.user_values[u_idx].keys().copied()
+    }
+
+    fn push_user(&mut self) {
+        self.user_values.push(HashMap::new());
+    }
+
+    /// Drops every pair of the user, the index is left empty so that the other indexes stay valid.
+    fn remove_user(&mut self, u_idx: usize) {
+        for p_idx in std::mem::take(self.user_values.get_mut(u_idx)).into_keys() {
+            self.post_users
+                .get_mut(p_idx)
+                .retain(|&other| other != u_idx);
+        }
+    }
+
+    /// Drops every pair of the post, the index is left empty so that the other indexes stay valid.
+    fn remove_post(&mut self, p_idx: usize) {
+        for u_idx in std::mem::take(self.post_users.get_mut(p_idx)) {
+            self.user_values.get_mut(u_idx).remove(&p_idx);
+        }
+    }
+
+    fn push_post(&mut self) {
+        self.post_users.push(Vec::new());
+    }
+
+    /// Every stored pair, ordered by user then post.
+    fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = self
+            .user_values
+            .iter()
+            .enumerate()
+            .flat_map(|(u_idx, values)| values.keys().map(move |&p_idx| (u_idx, p_idx)))
+            .collect::<Vec<_>>();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Stores up to `count` random posts the user has no pair with as negatives.
+    fn sample_user_negatives(&mut self, u_idx: usize, count: usize, n_observations: usize) {
+        let n_posts = self.post_users.len();
+        let target = (self.user_values[u_idx].len() + count).min(n_posts);
+        let mut rng = rand::thread_rng();
+        // bounded so that users paired with nearly every post do not loop for long
+        for _ in 0..count * 3 {
+            if self.user_values[u_idx].len() >= target {
+                break;
+            }
+            let p_idx = rng.gen_range(0..n_posts);
+            self.insert(u_idx, p_idx, vec![0.0; n_observations]);
+        }
+    }
+
+    /// Stores up to `count` random users the post has no pair with as negatives.
+    fn sample_post_negatives(&mut self, p_idx: usize, count: usize, n_observations: usize) {
+        let n_users = self.user_values.len();
+        let target = (self.post_users[p_idx].len() + count).min(n_users);
+        let mut rng = rand::thread_rng();
+        for _ in 0..count * 3 {
+            if self.post_users[p_idx].len() >= target {
+                break;
+            }
+            let u_idx = rng.gen_range(0..n_users);
+            self.insert(u_idx, p_idx, vec![0.0; n_observations]);
+        }
+    }
+}
+
+/// SGD matrix factorization of the observation channels.
+#[derive(Debug, Clone)]
 pub struct NDArrayAppState {
     pub user_embeddings: ArrayBase<OwnedRepr<f32>, Dim<[usize; 2]>>,
     pub post_embeddings: ArrayBase<OwnedRepr<f32>, Dim<[usize; 2]>>,
-    pub interactions_actual: ArrayBase<OwnedRepr<f32>, Dim<[usize; 3]>>,
-    alpha: f32,
-    lambda: f32,
-    epochs: usize,
-    k: usize,
-    user_index_hashmap: HashMap<i64, usize>,
-    post_index_hashmap: HashMap<i64, usize>,
+    interactions: SparseInteractions,
+    config: RecommenderConfig,
+    trained_at: DateTime<Utc>,
+    user_index_hashmap: Arc<HashMap<i64, usize>>,
+    post_index_hashmap: Arc<HashMap<i64, usize>>,
     next_u_index: usize,
     next_p_index: usize,
+    /// Added since the last refresh, trained on every pair they have.
+    pending_users: Vec<usize>,
+    pending_posts: Vec<usize>,
+    /// Observed since the last refresh, trained `online_steps` times.
+    pending_pairs: Vec<(usize, usize)>,
 }
 
 pub async fn load_models(
     pg_pool: &Pool<Postgres>,
-    alpha: f32,
-    lambda: f32,
-    epochs: usize,
-    k: usize,
-    observations: usize,
-) -> NDArrayAppState {
-    let join_result = build_model(pg_pool)
-        .await
-        .expect("Could not query for interactions matrix model");
-
-    let u_count = join_result
+    config: RecommenderConfig,
+) -> ModelResult<NDArrayAppState> {
+    let data = InteractionData::load(pg_pool).await?;
+    Ok(build_models(config, &data))
+}
+
+/// An untrained model of the users and posts in `data`, with only its observed pairs.
+pub fn build_models(config: RecommenderConfig, data: &InteractionData) -> NDArrayAppState {
+    let user_ids = &data.user_ids;
+    let post_ids = data.post_ids();
+    let observed = &data.observed;
+    let k = config.k_features;
+    let n_observations = config.channels.len();
+
+    let user_index_hashmap = user_ids
         .iter()
-        .unique_by(|q| q.user_id)
-        .collect::<Vec<_>>()
-        .len() as u64;
-    let v_count = join_result
+        .enumerate()
+        .map(|(idx, &id)| (id, idx))
+        .collect::<HashMap<_, _>>();
+    let post_index_hashmap = post_ids
         .iter()
-        .unique_by(|q| q.post_id)
-        .collect::<Vec<_>>()
-        .len() as u64;
-
-    let u = Array2::random((u_count as usize, k), Uniform::new(0.0, 1.0));
-    let v = Array2::random((v_count as usize, k), Uniform::new(0.0, 1.0));
-    let mut a_obs = Array3::zeros((u_count as usize, v_count as usize, observations));
+        .enumerate()
+        .map(|(idx, &id)| (id, idx))
+        .collect::<HashMap<_, _>>();
 
-    let mut user_index_hashmap = HashMap::<i64, usize>::new();
-    let mut post_index_hashmap = HashMap::<i64, usize>::new();
-    let mut next_u_index = 0;
-    let mut next_v_index = 0;
+    let u = Array2::random((user_ids.len(), k), Uniform::new(0.0, 1.0));
+    let v = Array2::random((post_ids.len(), k), Uniform::new(0.0, 1.0));
+    let mut interactions = SparseInteractions::new(user_ids.len(), post_ids.len());
 
-    for i in 0..join_result.len() {
-        if !user_index_hashmap.contains_key(&join_result[i].user_id) {
-            user_index_hashmap.insert(join_result[i].user_id, next_u_index);
-            next_u_index += 1;
-        }
-        if !post_index_hashmap.contains_key(&join_result[i].post_id) {
-            post_index_hashmap.insert(join_result[i].post_id, next_v_index);
-            next_v_index += 1;
+    for row in observed {
+        // users or posts created between the queries are picked up by the next load
+        let (Some(&u_index), Some(&v_index)) = (
+            user_index_hashmap.get(&row.user_id),
+            post_index_hashmap.get(&row.post_id),
+        ) else {
+            continue;
+        };
+        let signals = config.signals(row);
+        // pairs only seen through unused channels are left for negative sampling
+        if signals.iter().all(|&s| s == 0.0) {
+            continue;
         }
-        let u_index = user_index_hashmap[&join_result[i].user_id];
-        let v_index = post_index_hashmap[&join_result[i].post_id];
-        a_obs[(u_index, v_index, 0)] = join_result[i].is_liked as f32;
-        a_obs[(u_index, v_index, 1)] = join_result[i].is_following as f32;
+        interactions.insert(u_index, v_index, signals);
     }
 
-    // println!("{:?}", a_obs);
+    // users without any interactions still get negatives, like new users do
+    for u_index in 0..user_ids.len() {
+        let observed_count = interactions.user_values[u_index].len().max(1);
+        interactions.sample_user_negatives(
+            u_index,
+            observed_count * config.negative_ratio,
+            n_observations,
+        );
+    }
 
     NDArrayAppState {
         user_embeddings: u,
         post_embeddings: v,
-        interactions_actual: a_obs,
-        alpha,
-        epochs,
-        k,
-        lambda,
-        user_index_hashmap,
-        post_index_hashmap,
-        next_u_index,
-        next_p_index: next_v_index,
+        interactions,
+        config,
+        trained_at: Utc::now(),
+        next_u_index: user_ids.len(),
+        next_p_index: post_ids.len(),
+        user_index_hashmap: Arc::new(user_index_hashmap),
+        post_index_hashmap: Arc::new(post_index_hashmap),
+        pending_users: Vec::new(),
+        pending_posts: Vec::new(),
+        pending_pairs: Vec::new(),
     }
 }
 
 impl NDArrayAppState {
     pub fn train(&mut self) {
-        let a_shape = self.interactions_actual.shape().to_vec();
+        self.train_epochs(self.config.epochs);
+    }
+
+    /// Trains every stored pair `epochs` times, for training in steps e.g. with early stopping.
+    pub fn train_epochs(&mut self, epochs: usize) {
+        let pairs = self.interactions.pairs();
 
-        for _ in 0..self.epochs {
-            for u_idx in 0..a_shape[0] {
-                for p_idx in 0..a_shape[1] {
-                    self.update_embeddings(u_idx, p_idx);
+        for _ in 0..epochs {
+            for &(u_idx, p_idx) in &pairs {
+                self.update_embeddings(u_idx, p_idx);
+            }
+        }
+        self.trained_at = Utc::now();
+    }
+
+    /// Trains only the pairs involving the given user and post indexes, for rows added since the last full training.
+    pub fn train_rows(&mut self, u_indexes: &[usize], p_indexes: &[usize]) {
+        let mut pairs = Vec::new();
+        for &u_idx in u_indexes {
+            for p_idx in self.interactions.user_posts(u_idx) {
+                pairs.push((u_idx, p_idx));
+            }
+        }
+        for &p_idx in p_indexes {
+            for &u_idx in &self.interactions.post_users[p_idx] {
+                // pairs with new users were already collected above
+                if !u_indexes.contains(&u_idx) {
+                    pairs.push((u_idx, p_idx));
                 }
             }
         }
+
+        for _ in 0..self.config.epochs {
+            for &(u_idx, p_idx) in &pairs {
+                self.update_embeddings(u_idx, p_idx);
+            }
+        }
+    }
+
+    /// Runs `steps` passes of SGD over only the given pairs, for interactions that just happened.
+    pub fn train_pairs(&mut self, pairs: &[(usize, usize)], steps: usize) {
+        for _ in 0..steps {
+            for &(u_idx, p_idx) in pairs {
+                self.update_embeddings(u_idx, p_idx);
+            }
+        }
+    }
+
+    /// Sets the signal of one channel of a user and post, e.g. `ChannelKind::Like` to 1 when the user likes the post.
+    /// Returns the indexes of the pair, None if the channel is not used or the user or post is not in the model yet.
+    pub fn set_observation(
+        &mut self,
+        user_id: i64,
+        post_id: i64,
+        kind: ChannelKind,
+        signal: f32,
+    ) -> Option<(usize, usize)> {
+        let observation = self.config.channel_index(kind)?;
+        let u_index = *self.user_index_hashmap.get(&user_id)?;
+        let p_index = *self.post_index_hashmap.get(&post_id)?;
+        self.interactions.set(
+            u_index,
+            p_index,
+            observation,
+            signal,
+            self.config.channels.len(),
+        );
+        Some((u_index, p_index))
+    }
+
+    pub fn snapshot(&self) -> RecommenderSnapshot {
+        RecommenderSnapshot {
+            version: RecommenderSnapshot::VERSION,
+            trained_at: self.trained_at,
+            config: self.config.clone(),
+            user_embeddings: self.user_embeddings.clone(),
+            post_embeddings: self.post_embeddings.clone(),
+            user_index_hashmap: self.user_index_hashmap.as_ref().clone(),
+            post_index_hashmap: self.post_index_hashmap.as_ref().clone(),
+        }
+    }
+
+    /// Copies the learned embeddings of users and posts that are in the snapshot.
+    /// Returns the indexes of the users and posts that were not, which are still untrained.
+    pub fn apply_snapshot(&mut self, snapshot: &RecommenderSnapshot) -> (Vec<usize>, Vec<usize>) {
+        self.trained_at = snapshot.trained_at;
+        let new_users = Self::copy_rows(
+            &self.user_index_hashmap,
+            &mut self.user_embeddings,
+            &snapshot.user_index_hashmap,
+            &snapshot.user_embeddings,
+        );
+        let new_posts = Self::copy_rows(
+            &self.post_index_hashmap,
+            &mut self.post_embeddings,
+            &snapshot.post_index_hashmap,
+            &snapshot.post_embeddings,
+        );
+        (new_users, new_posts)
+    }
+
+    fn copy_rows(
+        index_hashmap: &HashMap<i64, usize>,
+        embeddings: &mut Array2<f32>,
+        snapshot_index_hashmap: &HashMap<i64, usize>,
+        snapshot_embeddings: &Array2<f32>,
+    ) -> Vec<usize> {
+        let mut missing = Vec::new();
+        for (id, &idx) in index_hashmap {
+            match snapshot_index_hashmap.get(id) {
+                Some(&snapshot_idx) => embeddings
+                    .row_mut(idx)
+                    .assign(&snapshot_embeddings.row(snapshot_idx)),
+                None => missing.push(idx),
+            }
+        }
+        missing.sort_unstable();
+        missing
     }
 
     fn update_embeddings(&mut self, u_idx: usize, p_idx: usize) {
-        let int_shape = self.interactions_actual.shape()[2];
-        for int_idx in 0..int_shape {
+        let Some(signals) = self.interactions.get(u_idx, p_idx) else {
+            return;
+        };
+        for (channel, &signal) in self.config.channels.iter().zip(signals) {
+            let (actual_interaction, weight) = channel.target_and_weight(signal);
             let predicted_interaction = self
                 .user_embeddings
                 .row(u_idx)
                 .dot(&self.post_embeddings.row(p_idx));
 
-            let error = self.interactions_actual[(u_idx, p_idx, int_idx)] - predicted_interaction;
+            let error = weight * (actual_interaction - predicted_interaction);
 
-            for f in 0..self.k {
-                self.user_embeddings[(u_idx, f)] += self.alpha
+            for f in 0..self.config.k_features {
+                self.user_embeddings[(u_idx, f)] += self.config.alpha
                     * (error * self.post_embeddings[(p_idx, f)]
-                        - self.lambda * self.user_embeddings[(u_idx, f)]);
-                self.post_embeddings[(p_idx, f)] += self.alpha
+                        - self.config.lambda * self.user_embeddings[(u_idx, f)]);
+                self.post_embeddings[(p_idx, f)] += self.config.alpha
                     * (error * self.user_embeddings[(u_idx, f)]
-                        - self.lambda * self.post_embeddings[(p_idx, f)]);
+                        - self.config.lambda * self.post_embeddings[(p_idx, f)]);
             }
         }
     }
 
-    pub fn predict(&self, user_id: i64, post_id: i64) -> f32 {
-        let u_index = self.user_index_hashmap[&user_id];
-        let p_index = self.post_index_hashmap[&post_id];
-        self.user_embeddings
-            .row(u_index)
-            .dot(&self.post_embeddings.row(p_index))
+    /// None when the user or post is not in the model, e.g. created since it was last published or removed.
+    pub fn predict(&self, user_id: i64, post_id: i64) -> Option<f32> {
+        let u_index = *self.user_index_hashmap.get(&user_id)?;
+        let p_index = *self.post_index_hashmap.get(&post_id)?;
+        Some(
+            self.user_embeddings
+                .row(u_index)
+                .dot(&self.post_embeddings.row(p_index)),
+        )
     }
 
-    /// returns the post ids in order of best to worst
-    pub fn predict_all(&self, user_id: i64, post_ids: &[i64]) -> Vec<(i64, f32)> {
-        let mut predictions = Vec::with_capacity(post_ids.len());
+    /// Returns the index of the new user, None if the user is already in the model.
+    fn push_user(&mut self, user_id: i64) -> Result<Option<usize>, ShapeError> {
+        if self.user_index_hashmap.contains_key(&user_id) {
+            return Ok(None);
+        }
+        let u_index = self.next_u_index;
+        let new_row = Array1::<f32>::random(self.config.k_features, Uniform::new(0.0, 1.0));
+        self.user_embeddings.push_row(new_row.view())?;
+        Arc::make_mut(&mut self.user_index_hashmap).insert(user_id, u_index);
+        self.next_u_index += 1;
+        self.interactions.push_user();
+        self.interactions.sample_user_negatives(
+            u_index,
+            self.config.negative_ratio,
+            self.config.channels.len(),
+        );
+        Ok(Some(u_index))
+    }
 
-        for &post_id in post_ids {
-            predictions.push((post_id, self.predict(user_id, post_id)));
+    /// Returns the index of the new post, None if the post is already in the model.
+    fn push_post(&mut self, post_id: i64) -> Result<Option<usize>, ShapeError> {
+        if self.post_index_hashmap.contains_key(&post_id) {
+            return Ok(None);
         }
+        let p_index = self.next_p_index;
+        let new_row = Array1::<f32>::random(self.config.k_features, Uniform::new(0.0, 1.0));
+        self.post_embeddings.push_row(new_row.view())?;
+        Arc::make_mut(&mut self.post_index_hashmap).insert(post_id, p_index);
+        self.next_p_index += 1;
+        self.interactions.push_post();
+        self.interactions.sample_post_negatives(
+            p_index,
+            self.config.negative_ratio,
+            self.config.channels.len(),
+        );
+        Ok(Some(p_index))
+    }
+}
 
-        predictions
+impl Recommender for NDArrayAppState {
+    fn fit(&mut self) {
+        self.train();
+        self.pending_users.clear();
+        self.pending_posts.clear();
+        self.pending_pairs.clear();
     }
 
-    pub fn add_user(&mut self, user_id: i64) -> Result<(), ShapeError> {
-        let new_row = Array1::<f32>::random(self.k, Uniform::new(0.0, 1.0));
-        self.user_index_hashmap.insert(user_id, self.next_u_index);
-        self.next_u_index += 1;
-        self.user_embeddings.push_row(new_row.view())?;
-        self.add_user_interaction()
+    fn score(&self, user_id: i64, post_ids: &[i64]) -> Vec<Option<f32>> {
+        post_ids
+            .iter()
+            .map(|&post_id| self.predict(user_id, post_id))
+            .collect()
     }
 
-    pub fn add_post(&mut self, post_id: i64) -> Result<(), ShapeError> {
-        let new_row = Array1::<f32>::random(self.k, Uniform::new(0.0, 1.0));
-        self.post_index_hashmap.insert(post_id, self.next_p_index);
-        self.next_p_index += 1;
-        self.post_embeddings.push_row(new_row.view())?;
-        self.add_post_interaction()
+    fn add_user(&mut self, user_id: i64) {
+        match self.push_user(user_id) {
+            Ok(u_index) => self.pending_users.extend(u_index),
+            Err(e) => println!("Could not add user {} to the recommender: {}", user_id, e),
+        }
+    }
+
+    fn add_post(&mut self, post_id: i64) {
+        match self.push_post(post_id) {
+            Ok(p_index) => self.pending_posts.extend(p_index),
+            Err(e) => println!("Could not add post {} to the recommender: {}", post_id, e),
+        }
     }
 
-    fn add_user_interaction(&mut self) -> Result<(), ShapeError> {
-        let shape = self.interactions_actual.shape();
-        let new_interaction = Array2::<f32>::zeros((shape[1], shape[2]));
-        self.interactions_actual
-            .push(Axis(0), new_interaction.view())
+    /// The embedding row is left unused until the next full load.
+    fn remove_user(&mut self, user_id: i64) {
+        if let Some(u_index) = Arc::make_mut(&mut self.user_index_hashmap).remove(&user_id) {
+            self.interactions.remove_user(u_index);
+        }
+    }
+
+    fn remove_post(&mut self, post_id: i64) {
+        if let Some(p_index) = Arc::make_mut(&mut self.post_index_hashmap).remove(&post_id) {
+            self.interactions.remove_post(p_index);
+        }
+    }
+
+    fn observe(&mut self, user_id: i64, post_id: i64, kind: ChannelKind, signal: f32) {
+        let pair = self.set_observation(user_id, post_id, kind, signal);
+        self.pending_pairs.extend(pair);
+    }
+
+    fn refresh(&mut self) {
+        let users = std::mem::take(&mut self.pending_users);
+        let posts = std::mem::take(&mut self.pending_posts);
+        let mut pairs = std::mem::take(&mut self.pending_pairs);
+        pairs.sort_unstable();
+        pairs.dedup();
+        self.train_rows(&users, &posts);
+        self.train_pairs(&pairs, self.config.online_steps);
+    }
+
+    fn clone_box(&self) -> Box<dyn Recommender> {
+        Box::new(self.clone())
+    }
+
+    /// Starts from the previous embeddings, which converges faster and keeps feeds stable between retrains.
+    fn warm_start(&mut self, previous: &dyn Recommender) {
+        if let Some(snapshot) = previous.snapshot() {
+            if snapshot.config.k_features == self.config.k_features {
+                self.apply_snapshot(&snapshot);
+            }
+        }
     }
 
-    fn add_post_interaction(&mut self) -> Result<(), ShapeError> {
-        let shape = self.interactions_actual.shape();
-        let new_interaction = Array2::<f32>::zeros((shape[0], shape[2]));
-        self.interactions_actual
-            .push(Axis(1), new_interaction.view())
+    fn snapshot(&self) -> Option<RecommenderSnapshot> {
+        Some(NDArrayAppState::snapshot(self))
     }
 }
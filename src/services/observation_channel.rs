@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::interactions_matrix_model::InteractionsMatrixModel;
+
+/// A kind of interaction between a user and a post that the recommender learns from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    Like,
+    /// The user follows the author of the post.
+    Follow,
+    /// The post was shown to the user who did not like it, the model learns to predict 0 for it.
+    SeenNotLiked,
+    Comment,
+    /// The user visited the profile of the author of the post.
+    ProfileVisit,
+    Dwell,
+}
+
+impl ChannelKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelKind::Like => "like",
+            ChannelKind::Follow => "follow",
+            ChannelKind::SeenNotLiked => "seen_not_liked",
+            ChannelKind::Comment => "comment",
+            ChannelKind::ProfileVisit => "profile_visit",
+            ChannelKind::Dwell => "dwell",
+        }
+    }
+
+    /// Negative channels are trained towards 0 even when observed.
+    fn is_negative(&self) -> bool {
+        matches!(self, ChannelKind::SeenNotLiked)
+    }
+
+    /// How strongly the pair shows this interaction, 0 when it does not.
+    /// Counts and durations are log scaled so that a few heavy users do not dominate.
+    pub fn signal(&self, row: &InteractionsMatrixModel) -> f32 {
+        match self {
+            ChannelKind::Like => row.is_liked as f32,
+            ChannelKind::Follow => row.is_following as f32,
+            ChannelKind::SeenNotLiked => (row.is_seen == 1 && row.is_liked == 0) as i32 as f32,
+            ChannelKind::Comment => (row.comments as f32).ln_1p(),
+            ChannelKind::ProfileVisit => (row.profile_visits as f32).ln_1p(),
+            ChannelKind::Dwell => (row.dwell_ms as f32 / 1000.0).ln_1p(),
+        }
+    }
+}
+
+impl FromStr for ChannelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "like" => Ok(ChannelKind::Like),
+            "follow" => Ok(ChannelKind::Follow),
+            "seen_not_liked" => Ok(ChannelKind::SeenNotLiked),
+            "comment" => Ok(ChannelKind::Comment),
+            "profile_visit" => Ok(ChannelKind::ProfileVisit),
+            "dwell" => Ok(ChannelKind::Dwell),
+            other => Err(format!("unknown observation channel {}", other)),
+        }
+    }
+}
+
+/// One observation of every user and post pair, with its weight in the loss.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ObservationChannel {
+    pub kind: ChannelKind,
+    /// Scales the squared error of this channel.
+    pub weight: f32,
+    /// Extra weight per unit of signal, so that stronger interactions are trusted more.
+    pub confidence: f32,
+}
+
+impl ObservationChannel {
+    /// The value the model should predict for a pair with the given signal, and the weight of its error.
+    pub fn target_and_weight(&self, signal: f32) -> (f32, f32) {
+        let target = if signal > 0.0 && !self.kind.is_negative() {
+            1.0
+        } else {
+            0.0
+        };
+        (target, self.weight * (1.0 + self.confidence * signal))
+    }
+
+    /// The weighted signal for models without a loss per channel, 0 for negative channels.
+    pub fn implicit_strength(&self, signal: f32) -> f32 {
+        if self.kind.is_negative() {
+            0.0
+        } else {
+            self.weight * signal
+        }
+    }
+
+    /// Likes and follows with equal weights and no confidence scaling.
+    pub fn defaults() -> Vec<ObservationChannel> {
+        vec![
+            ObservationChannel::new(ChannelKind::Like),
+            ObservationChannel::new(ChannelKind::Follow),
+        ]
+    }
+
+    fn new(kind: ChannelKind) -> Self {
+        Self {
+            kind,
+            weight: 1.0,
+            confidence: 0.0,
+        }
+    }
+
+    /// Parses "name[:weight[:confidence]],...", e.g. "like:1,follow:0.5,seen_not_liked:0.2,dwell:0.3:0.5".
+    pub fn parse_list(s: &str) -> Result<Vec<ObservationChannel>, String> {
+        let mut channels = Vec::<ObservationChannel>::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut fields = part.split(':');
+            let mut channel = ObservationChannel::new(fields.next().unwrap_or_default().parse()?);
+            if let Some(weight) = fields.next() {
+                channel.weight = weight
+                    .parse()
+                    .map_err(|_| format!("invalid weight in {}", part))?;
+            }
+            if let Some(confidence) = fields.next() {
+                channel.confidence = confidence
+                    .parse()
+                    .map_err(|_| format!("invalid confidence in {}", part))?;
+            }
+            if fields.next().is_some() {
+                return Err(format!("too many fields in {}", part));
+            }
+            if channels.iter().any(|c| c.kind == channel.kind) {
+                return Err(format!("{} is listed twice", channel.kind.name()));
+            }
+            channels.push(channel);
+        }
+        if channels.is_empty() {
+            return Err("at least one channel is required".to_string());
+        }
+        Ok(channels)
+    }
+}
@@ -0,0 +1,269 @@
+use std::{env, sync::RwLock};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use lib_routes::error::RouteError;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+pub type OidcResult<T> = Result<T, OidcError>;
+
+#[derive(Debug)]
+pub enum OidcError {
+    Http(String),
+    Discovery(String),
+    TokenExchange(String),
+    InvalidIdToken(String),
+}
+
+impl From<OidcError> for RouteError {
+    fn from(value: OidcError) -> Self {
+        match value {
+            OidcError::InvalidIdToken(_) => RouteError::InvalidAuth,
+            e => RouteError::IdentityProvider(format!("{:?}", e)),
+        }
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(value: reqwest::Error) -> Self {
+        OidcError::Http(value.to_string())
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(value: jsonwebtoken::errors::Error) -> Self {
+        OidcError::InvalidIdToken(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    /// None for public clients, which rely on PKCE alone.
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+}
+
+impl OidcConfig {
+    /// Reads the provider from OIDC_* env vars, None if OIDC_ISSUER is not set.
+    pub fn from_env() -> Option<Self> {
+        let issuer = env::var("OIDC_ISSUER").ok()?;
+        Some(Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: env::var("OIDC_CLIENT_ID").expect("Could not get OIDC_CLIENT_ID"),
+            client_secret: env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_uri: env::var("OIDC_REDIRECT_URI").expect("Could not get OIDC_REDIRECT_URI"),
+            scopes: env::var("OIDC_SCOPES").unwrap_or("openid email profile".to_string()),
+        })
+    }
+}
+
+/// The parts of the provider's discovery document that are used.
+#[derive(Deserialize, Debug)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    /// Some providers send a bool and others the string "true".
+    email_verified: Option<serde_json::Value>,
+    pub preferred_username: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+impl IdTokenClaims {
+    pub fn email_verified(&self) -> bool {
+        match &self.email_verified {
+            Some(serde_json::Value::Bool(b)) => *b,
+            Some(serde_json::Value::String(s)) => s == "true",
+            _ => false,
+        }
+    }
+}
+
+/// An OpenID Connect provider found through discovery, with its signing keys cached.
+#[derive(Debug)]
+pub struct OidcProvider {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+    jwks: RwLock<JwkSet>,
+}
+
+impl OidcProvider {
+    /// Fetches the provider's discovery document and signing keys.
+    pub async fn discover(config: OidcConfig) -> OidcResult<Self> {
+        let http = reqwest::Client::new();
+        let metadata = http
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                config.issuer
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ProviderMetadata>()
+            .await?;
+        if metadata.issuer.trim_end_matches('/') != config.issuer {
+            return Err(OidcError::Discovery(format!(
+                "issuer {} does not match configured issuer {}",
+                metadata.issuer, config.issuer
+            )));
+        }
+        let jwks = Self::fetch_jwks(&http, &metadata.jwks_uri).await?;
+        Ok(Self {
+            config,
+            metadata,
+            http,
+            jwks: RwLock::new(jwks),
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.metadata.issuer
+    }
+
+    async fn fetch_jwks(http: &reqwest::Client, jwks_uri: &str) -> OidcResult<JwkSet> {
+        let jwks = http
+            .get(jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?;
+        Ok(jwks)
+    }
+
+    /// The url to send the user to, using the S256 PKCE method.
+    pub fn authorization_url(&self, state: &str, nonce: &str, code_verifier: &str) -> String {
+        let mut url = Url::parse(&self.metadata.authorization_endpoint)
+            .expect("Invalid authorization_endpoint from OIDC discovery");
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &pkce_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        url.to_string()
+    }
+
+    /// Exchanges an authorization code for the user's id token.
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str) -> OidcResult<String> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let res = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(OidcError::TokenExchange(format!("{}: {}", status, body)));
+        }
+        Ok(res.json::<TokenResponse>().await?.id_token)
+    }
+
+    /// Finds the key an id token was signed with, refetching the keys once in case the provider rotated them.
+    async fn signing_key(&self, kid: Option<&str>) -> OidcResult<Jwk> {
+        let find = |jwks: &JwkSet| match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+            None => None,
+        };
+        if let Some(jwk) = find(&self.jwks.read().expect("err locking")) {
+            return Ok(jwk);
+        }
+        let jwks = Self::fetch_jwks(&self.http, &self.metadata.jwks_uri).await?;
+        let jwk = find(&jwks);
+        *self.jwks.write().expect("err locking") = jwks;
+        jwk.ok_or(OidcError::InvalidIdToken("unknown signing key".to_string()))
+    }
+
+    /// Validates the id token's signature, issuer, audience, expiry and nonce.
+    pub async fn validate_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> OidcResult<IdTokenClaims> {
+        let header = decode_header(id_token)?;
+        // symmetric algorithms would let anyone holding the client secret forge tokens
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(OidcError::InvalidIdToken(format!(
+                "unsupported alg {:?}",
+                header.alg
+            )));
+        }
+        let jwk = self.signing_key(header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)?.claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch".to_string()));
+        }
+        Ok(claims)
+    }
+}
+
+/// The S256 PKCE challenge for a code verifier.
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Discovers the provider configured with OIDC_* env vars.
+/// Returns None if OIDC is not configured or the provider could not be reached.
+pub async fn create_oidc_provider() -> Option<OidcProvider> {
+    let config = OidcConfig::from_env()?;
+    let issuer = config.issuer.clone();
+    match OidcProvider::discover(config).await {
+        Ok(provider) => Some(provider),
+        Err(e) => {
+            println!(
+                "OIDC login disabled, could not discover {}: {:?}",
+                issuer, e
+            );
+            None
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use argon2::password_hash;
+use lib_hash::{error::HashError, hash_scheme::HashScheme, pepper};
+use lib_routes::error::{RouteError, RouterResult};
+use sqlb::Fields;
+use sqlx::{prelude::FromRow, PgPool};
+
+use crate::models::{
+    base,
+    user_model::{update_password, UpdatePasswordModel, UserModel},
+};
+
+#[derive(FromRow, Fields, Debug)]
+pub struct HashModel {
+    pub username: String,
+    pub hash_scheme: HashScheme,
+    pub pwd_hash: String,
+    pub pwd_salt: String,
+    pub pepper_id: Option<i16>,
+}
+
+pub async fn get_hash_model(pool: &PgPool, username: &str) -> RouterResult<Option<HashModel>> {
+    let hash_model = base::get_one::<UserModel, HashModel, _>("username", username, pool).await?;
+    Ok(hash_model)
+}
+
+/// Verifies a password against the stored hash, returning LoginFail if it does not match.
+/// Hashes made with an old scheme, cost or pepper are upgraded once verified.
+pub async fn verify_password(
+    pool: &PgPool,
+    hash_model: &HashModel,
+    password: &str,
+) -> RouterResult<()> {
+    let hasher = hash_model.hash_scheme.hasher();
+    let pepper = hash_model.pepper_id.map(pepper::get).transpose()?;
+    hasher
+        .verify_peppered(password, pepper, &hash_model.pwd_salt, &hash_model.pwd_hash)
+        .map_err(|e| match e {
+            HashError::VerificationFail
+            | HashError::Argon2Error(password_hash::Error::Password) => RouteError::LoginFail,
+            e => e.into(),
+        })?;
+
+    let pepper_outdated = hash_model.pepper_id != pepper::current().map(|p| p.id());
+    if hash_model.hash_scheme.needs_rehash()
+        || hasher.hash_outdated(&hash_model.pwd_hash)
+        || pepper_outdated
+    {
+        set_password(pool, &hash_model.username, password).await?;
+    }
+
+    Ok(())
+}
+
+/// Hashes a password with `HashScheme::CURRENT` and the current pepper, replacing the user's password.
+pub async fn set_password(pool: &PgPool, username: &str, password: &str) -> RouterResult<()> {
+    let pepper = pepper::current();
+    let (pwd_hash, pwd_salt) = HashScheme::CURRENT
+        .hasher()
+        .hash_peppered(password, pepper)?;
+    let update = UpdatePasswordModel {
+        pwd_hash,
+        pwd_salt,
+        hash_scheme: HashScheme::CURRENT,
+        pepper_id: pepper.map(|p| p.id()),
+    };
+    update_password(username, update, pool).await?;
+    Ok(())
+}
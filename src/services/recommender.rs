@@ -0,0 +1,266 @@
+use std::{sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use sqlx::PgPool;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time::{interval, interval_at, Instant, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    libs::env::env_or,
+    services::{
+        observation_channel::ChannelKind,
+        recommender_snapshot::SnapshotStore,
+        recommenders::{load_recommender_model, Recommender, RecommenderConfig},
+    },
+};
+
+#[derive(Debug)]
+enum RecommenderEvent {
+    AddUser(i64),
+    AddPost(i64),
+    RemoveUser(i64),
+    RemovePost(i64),
+    Like {
+        user_id: i64,
+        post_id: i64,
+        liked: bool,
+    },
+    /// `post_ids` are the posts of the followed user.
+    Follow {
+        user_id: i64,
+        post_ids: Vec<i64>,
+        following: bool,
+    },
+}
+
+/// The published recommender model, readable without a lock.
+/// All changes go through a single background task which builds a new model and swaps it in.
+#[derive(Debug)]
+pub struct RecommenderHandle {
+    current: ArcSwap<Box<dyn Recommender>>,
+    events: UnboundedSender<RecommenderEvent>,
+}
+
+impl RecommenderHandle {
+    /// The currently published model, it is never modified after being published.
+    pub fn model(&self) -> Arc<Box<dyn Recommender>> {
+        self.current.load_full()
+    }
+
+    /// Queues a new user, the model cannot score posts for them until the next publish.
+    pub fn add_user(&self, user_id: i64) {
+        self.send(RecommenderEvent::AddUser(user_id));
+    }
+
+    /// Queues a new post, the model cannot score it until the next publish.
+    pub fn add_post(&self, post_id: i64) {
+        self.send(RecommenderEvent::AddPost(post_id));
+    }
+
+    /// Queues the removal of a deleted user.
+    pub fn remove_user(&self, user_id: i64) {
+        self.send(RecommenderEvent::RemoveUser(user_id));
+    }
+
+    /// Queues the removal of a deleted or hidden post.
+    pub fn remove_post(&self, post_id: i64) {
+        self.send(RecommenderEvent::RemovePost(post_id));
+    }
+
+    /// Queues a like or unlike, the model is refreshed for it at the next publish.
+    pub fn like(&self, user_id: i64, post_id: i64, liked: bool) {
+        self.send(RecommenderEvent::Like {
+            user_id,
+            post_id,
+            liked,
+        });
+    }
+
+    /// Queues a follow or unfollow of the author of `post_ids`.
+    pub fn follow(&self, user_id: i64, post_ids: Vec<i64>, following: bool) {
+        self.send(RecommenderEvent::Follow {
+            user_id,
+            post_ids,
+            following,
+        });
+    }
+
+    fn send(&self, event: RecommenderEvent) {
+        if self.events.send(event).is_err() {
+            println!("Recommender task stopped, dropping event");
+        }
+    }
+}
+
+/// How often the background task applies events and retrains.
+#[derive(Debug, Clone, Copy)]
+struct Schedule {
+    flush_every: Duration,
+    /// None when retraining is disabled.
+    retrain_every: Option<Duration>,
+}
+
+impl Schedule {
+    /// RECOMMENDER_EVENT_FLUSH_SECONDS and RECOMMENDER_RETRAIN_MINUTES, 0 disables retraining.
+    fn from_env() -> Self {
+        Self {
+            flush_every: Duration::from_secs(env_or("RECOMMENDER_EVENT_FLUSH_SECONDS", 5).max(1)),
+            retrain_every: match env_or("RECOMMENDER_RETRAIN_MINUTES", 60) {
+                0 => None,
+                minutes => Some(Duration::from_secs(minutes * 60)),
+            },
+        }
+    }
+}
+
+/// Publishes the model and starts the task that applies queued events and retrains on a schedule.
+pub fn spawn_recommender(
+    pool: PgPool,
+    config: RecommenderConfig,
+    store: SnapshotStore,
+    model: Box<dyn Recommender>,
+) -> Arc<RecommenderHandle> {
+    let (events, receiver) = mpsc::unbounded_channel();
+    let recommender = Arc::new(RecommenderHandle {
+        current: ArcSwap::from_pointee(model),
+        events,
+    });
+
+    tokio::spawn(run(
+        recommender.clone(),
+        receiver,
+        pool,
+        config,
+        store,
+        Schedule::from_env(),
+    ));
+    recommender
+}
+
+async fn run(
+    recommender: Arc<RecommenderHandle>,
+    mut receiver: UnboundedReceiver<RecommenderEvent>,
+    pool: PgPool,
+    config: RecommenderConfig,
+    store: SnapshotStore,
+    schedule: Schedule,
+) {
+    let mut flush = interval(schedule.flush_every);
+    flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut retrain = schedule.retrain_every.map(|period| {
+        // the first tick would be immediate, but the model was just trained at boot
+        let mut retrain = interval_at(Instant::now() + period, period);
+        retrain.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        retrain
+    });
+
+    let mut pending = Vec::new();
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Some(event) => pending.push(event),
+                None => return,
+            },
+            _ = flush.tick(), if !pending.is_empty() => {
+                publish_events(&recommender, std::mem::take(&mut pending)).await;
+            }
+            _ = tick(&mut retrain) => {
+                retrain_model(&recommender, &pool, config.clone(), &store).await;
+            }
+        }
+    }
+}
+
+/// Never completes when retraining is disabled.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Clones the published model, applies the queued events, refreshes it for them and swaps the clone in.
+/// The clone shares the interactions and index maps the events do not touch, see `SharedRows`.
+async fn publish_events(recommender: &RecommenderHandle, events: Vec<RecommenderEvent>) {
+    let mut model = recommender.model().clone_box();
+    for event in events {
+        match event {
+            RecommenderEvent::AddUser(id) => model.add_user(id),
+            RecommenderEvent::AddPost(id) => model.add_post(id),
+            RecommenderEvent::RemoveUser(id) => model.remove_user(id),
+            RecommenderEvent::RemovePost(id) => model.remove_post(id),
+            RecommenderEvent::Like {
+                user_id,
+                post_id,
+                liked,
+            } => {
+                let signal = if liked { 1.0 } else { 0.0 };
+                model.observe(user_id, post_id, ChannelKind::Like, signal);
+                // liked posts are marked seen, so an unliked one is seen and not liked like in a full load
+                model.observe(user_id, post_id, ChannelKind::SeenNotLiked, 1.0 - signal);
+            }
+            RecommenderEvent::Follow {
+                user_id,
+                post_ids,
+                following,
+            } => {
+                let signal = if following { 1.0 } else { 0.0 };
+                for post_id in post_ids {
+                    model.observe(user_id, post_id, ChannelKind::Follow, signal);
+                }
+            }
+        }
+    }
+
+    let refreshed = tokio::task::spawn_blocking(move || {
+        model.refresh();
+        model
+    })
+    .await;
+    match refreshed {
+        Ok(model) => recommender.current.store(Arc::new(model)),
+        Err(e) => println!("Recommender update failed: {}", e),
+    }
+}
+
+/// Trains a new model from all interactions off the request path, then swaps it in and saves a snapshot.
+async fn retrain_model(
+    recommender: &RecommenderHandle,
+    pool: &PgPool,
+    config: RecommenderConfig,
+    store: &SnapshotStore,
+) {
+    let mut model = match load_recommender_model(pool, config).await {
+        Ok(model) => model,
+        Err(e) => {
+            println!("Could not load interactions for retraining: {:?}", e);
+            return;
+        }
+    };
+    model.warm_start(recommender.model().as_ref().as_ref());
+
+    let trained = tokio::task::spawn_blocking(move || {
+        model.fit();
+        model
+    })
+    .await;
+    let model = match trained {
+        Ok(model) => model,
+        Err(e) => {
+            println!("Recommender retraining failed: {}", e);
+            return;
+        }
+    };
+
+    let snapshot = model.snapshot();
+    recommender.current.store(Arc::new(model));
+    if let Some(snapshot) = snapshot {
+        if let Err(e) = store.save(&snapshot).await {
+            println!("Could not save recommender snapshot: {}", e);
+        }
+    }
+}
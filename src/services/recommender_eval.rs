@@ -0,0 +1,405 @@
+use std::collections::{HashMap, HashSet};
+
+use lib_models::error::ModelResult;
+use rand::Rng;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{
+    models::interactions_matrix_model::{get_likes_by_time, LikeEventModel},
+    services::{
+        ndarray::{build_models, NDArrayAppState},
+        recommenders::{new_recommender, InteractionData, Recommender, RecommenderConfig},
+    },
+};
+
+/// Interactions split at a point in time, the likes after it are held out for testing or validation.
+pub struct EvaluationData {
+    /// All users and posts, with the observed pairs without the held out ones.
+    train: InteractionData,
+    post_ids: Vec<i64>,
+    train_likes: HashMap<i64, HashSet<i64>>,
+    test_likes: HashMap<i64, HashSet<i64>>,
+    n_train_likes: usize,
+    n_test_likes: usize,
+}
+
+impl EvaluationData {
+    /// Holds out the newest `test_fraction` of likes.
+    pub async fn load(pool: &PgPool, test_fraction: f64) -> ModelResult<Self> {
+        let data = InteractionData::load(pool).await?;
+        let likes = get_likes_by_time(pool).await?;
+        let n_train_likes = likes.len() - fraction_of(likes.len(), test_fraction);
+        Ok(Self::split(data, &likes, n_train_likes, likes.len()))
+    }
+
+    /// Splits the likes by time into train, validation and test, for picking hyperparameters without seeing the test likes.
+    /// The first holds out the validation likes and leaves the test likes out entirely,
+    /// the second trains on train and validation and holds out the test likes.
+    pub async fn load_with_validation(
+        pool: &PgPool,
+        validation_fraction: f64,
+        test_fraction: f64,
+    ) -> ModelResult<(Self, Self)> {
+        let data = InteractionData::load(pool).await?;
+        let likes = get_likes_by_time(pool).await?;
+        let n_test_likes = fraction_of(likes.len(), test_fraction);
+        let n_validation_likes = fraction_of(likes.len(), validation_fraction);
+        let n_train_likes = likes
+            .len()
+            .saturating_sub(n_test_likes + n_validation_likes);
+        let validation = Self::split(
+            data.clone(),
+            &likes,
+            n_train_likes,
+            likes.len() - n_test_likes,
+        );
+        let test = Self::split(data, &likes, likes.len() - n_test_likes, likes.len());
+        Ok((validation, test))
+    }
+
+    /// Trains on `likes[..train_end]` and holds out `likes[train_end..held_out_end]`.
+    /// Later likes are left out of both, so that nothing after the held out likes is trained on.
+    fn split(
+        mut train: InteractionData,
+        likes: &[LikeEventModel],
+        train_end: usize,
+        held_out_end: usize,
+    ) -> Self {
+        let mut train_likes = HashMap::<i64, HashSet<i64>>::new();
+        let mut test_likes = HashMap::<i64, HashSet<i64>>::new();
+        let mut excluded = HashSet::<(i64, i64)>::new();
+        for (i, like) in likes.iter().enumerate() {
+            if i < train_end {
+                train_likes
+                    .entry(like.user_id)
+                    .or_default()
+                    .insert(like.post_id);
+            } else {
+                if i < held_out_end {
+                    test_likes
+                        .entry(like.user_id)
+                        .or_default()
+                        .insert(like.post_id);
+                }
+                excluded.insert((like.user_id, like.post_id));
+            }
+        }
+
+        // the whole pair is held out, its other signals (seen, dwell, comments) can give the like away
+        train
+            .observed
+            .retain(|row| !excluded.contains(&(row.user_id, row.post_id)));
+
+        Self {
+            post_ids: train.post_ids(),
+            train,
+            train_likes,
+            test_likes,
+            n_train_likes: train_end,
+            n_test_likes: held_out_end - train_end,
+        }
+    }
+
+    /// Number of held out likes, 0 when there were too few likes to hold any out.
+    pub fn held_out_likes(&self) -> usize {
+        self.n_test_likes
+    }
+
+    /// The posts a user could be recommended, everything they have not liked before the split.
+    fn candidates(&self, user_id: i64) -> Vec<i64> {
+        let liked = self.train_likes.get(&user_id);
+        self.post_ids
+            .iter()
+            .copied()
+            .filter(|post_id| !liked.is_some_and(|l| l.contains(post_id)))
+            .collect()
+    }
+
+    /// Scores the top `k` candidates of every user with held out likes.
+    /// `score` returns one score per candidate post, higher is better.
+    fn rank(&self, k: usize, mut score: impl FnMut(i64, &[i64]) -> Vec<f32>) -> RankingMetrics {
+        let mut metrics = RankingMetrics::default();
+        let mut recommended = HashSet::<i64>::new();
+        let mut users = 0;
+
+        for (&user_id, relevant) in &self.test_likes {
+            let candidates = self.candidates(user_id);
+            let scores = score(user_id, &candidates);
+            let mut ranked = candidates.into_iter().zip(scores).collect::<Vec<_>>();
+            ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            ranked.truncate(k);
+
+            let mut hits = 0;
+            let mut dcg = 0.0;
+            for (rank, (post_id, _)) in ranked.iter().enumerate() {
+                recommended.insert(*post_id);
+                if relevant.contains(post_id) {
+                    hits += 1;
+                    dcg += 1.0 / (rank as f64 + 2.0).log2();
+                }
+            }
+            let ideal_dcg = (0..relevant.len().min(k))
+                .map(|rank| 1.0 / (rank as f64 + 2.0).log2())
+                .sum::<f64>();
+
+            metrics.precision_at_k += hits as f64 / k as f64;
+            metrics.recall_at_k += hits as f64 / relevant.len() as f64;
+            metrics.ndcg_at_k += dcg / ideal_dcg;
+            users += 1;
+        }
+
+        if users > 0 {
+            metrics.precision_at_k /= users as f64;
+            metrics.recall_at_k /= users as f64;
+            metrics.ndcg_at_k /= users as f64;
+        }
+        if !self.post_ids.is_empty() {
+            metrics.coverage = recommended.len() as f64 / self.post_ids.len() as f64;
+        }
+        metrics
+    }
+
+    /// Trains a model of the configured kind on the likes before the split and ranks with its scores.
+    pub fn evaluate_model(&self, config: RecommenderConfig, k: usize) -> RankingMetrics {
+        let mut model = new_recommender(config, &self.train);
+        model.fit();
+        self.rank_model(model.as_ref(), k)
+    }
+
+    pub fn rank_model(&self, model: &dyn Recommender, k: usize) -> RankingMetrics {
+        // unknown posts rank last, the feed would give them the cold start score instead
+        self.rank(k, |user_id, candidates| {
+            model
+                .score(user_id, candidates)
+                .into_iter()
+                .map(|score| score.unwrap_or(f32::NEG_INFINITY))
+                .collect()
+        })
+    }
+
+    /// Ranks in a random order, the floor any model should beat.
+    pub fn evaluate_random(&self, k: usize) -> RankingMetrics {
+        let mut rng = rand::thread_rng();
+        self.rank(k, |_, candidates| {
+            candidates.iter().map(|_| rng.gen::<f32>()).collect()
+        })
+    }
+
+    /// Ranks the posts with the most likes before the split first, for every user.
+    pub fn evaluate_popularity(&self, k: usize) -> RankingMetrics {
+        let mut like_counts = HashMap::<i64, f32>::new();
+        for post_id in self.train_likes.values().flatten() {
+            *like_counts.entry(*post_id).or_default() += 1.0;
+        }
+        self.rank(k, |_, candidates| {
+            candidates
+                .iter()
+                .map(|post_id| like_counts.get(post_id).copied().unwrap_or_default())
+                .collect()
+        })
+    }
+
+    /// The held out likes with target 1, and `negative_ratio` random unliked posts per like with target 0.
+    pub fn validation_pairs(&self, negative_ratio: usize) -> Vec<(i64, i64, f32)> {
+        let mut rng = rand::thread_rng();
+        let mut pairs = Vec::new();
+        for (&user_id, relevant) in &self.test_likes {
+            let liked = self.train_likes.get(&user_id);
+            for &post_id in relevant {
+                pairs.push((user_id, post_id, 1.0));
+            }
+            let wanted = relevant.len() * negative_ratio;
+            let mut negatives = 0;
+            // bounded so that users who liked nearly every post do not loop for long
+            for _ in 0..wanted * 3 {
+                if negatives >= wanted || self.post_ids.is_empty() {
+                    break;
+                }
+                let post_id = self.post_ids[rng.gen_range(0..self.post_ids.len())];
+                if !relevant.contains(&post_id) && !liked.is_some_and(|l| l.contains(&post_id)) {
+                    pairs.push((user_id, post_id, 0.0));
+                    negatives += 1;
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Trains one epoch at a time until the mean squared error on `validation` has not improved for
+    /// `patience` epochs, returning the model at its best epoch with the number of epochs and its loss.
+    pub fn train_with_early_stopping(
+        &self,
+        config: RecommenderConfig,
+        validation: &[(i64, i64, f32)],
+        max_epochs: usize,
+        patience: usize,
+    ) -> (NDArrayAppState, usize, f32) {
+        let mut model = build_models(config, &self.train);
+        let mut best = (model.clone(), 0, validation_loss(&model, validation));
+        for epoch in 1..=max_epochs {
+            model.train_epochs(1);
+            let loss = validation_loss(&model, validation);
+            // diverging learning rates end up as NaN, which never counts as an improvement
+            if loss < best.2 {
+                best = (model.clone(), epoch, loss);
+            } else if epoch - best.1 >= patience {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Evaluates the model with `config` and both baselines.
+    pub fn evaluate(
+        &self,
+        config: RecommenderConfig,
+        k: usize,
+        test_fraction: f64,
+    ) -> EvaluationReport {
+        EvaluationReport {
+            k,
+            test_fraction,
+            train_likes: self.n_train_likes,
+            test_likes: self.n_test_likes,
+            users_evaluated: self.test_likes.len(),
+            model: self.evaluate_model(config.clone(), k),
+            random: self.evaluate_random(k),
+            popularity: self.evaluate_popularity(k),
+            config,
+        }
+    }
+}
+
+fn fraction_of(n: usize, fraction: f64) -> usize {
+    ((n as f64 * fraction).round() as usize).min(n)
+}
+
+/// Mean squared error of the predictions of `(user_id, post_id, target)` pairs.
+pub fn validation_loss(model: &NDArrayAppState, validation: &[(i64, i64, f32)]) -> f32 {
+    if validation.is_empty() {
+        return 0.0;
+    }
+    let total = validation
+        .iter()
+        .map(|&(user_id, post_id, target)| {
+            (target - model.predict(user_id, post_id).unwrap_or_default()).powi(2)
+        })
+        .sum::<f32>();
+    total / validation.len() as f32
+}
+
+/// Averages over the users with held out likes, except coverage which is the
+/// fraction of all posts recommended to at least one of them.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RankingMetrics {
+    pub precision_at_k: f64,
+    pub recall_at_k: f64,
+    pub ndcg_at_k: f64,
+    pub coverage: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvaluationReport {
+    pub k: usize,
+    pub test_fraction: f64,
+    pub config: RecommenderConfig,
+    pub train_likes: usize,
+    pub test_likes: usize,
+    pub users_evaluated: usize,
+    pub model: RankingMetrics,
+    pub random: RankingMetrics,
+    pub popularity: RankingMetrics,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::models::interactions_matrix_model::{InteractionsMatrixModel, PostTimeModel};
+
+    fn like(user_id: i64, post_id: i64) -> LikeEventModel {
+        LikeEventModel { user_id, post_id }
+    }
+
+    fn liked_row(user_id: i64, post_id: i64) -> InteractionsMatrixModel {
+        InteractionsMatrixModel {
+            user_id,
+            post_id,
+            is_liked: 1,
+            is_following: 0,
+            is_seen: 1,
+            comments: 0,
+            profile_visits: 0,
+            dwell_ms: 0,
+        }
+    }
+
+    /// Likes oldest first, trained on the first two, (1, 11) held out and (2, 12) after the held out likes.
+    fn evaluation_data() -> EvaluationData {
+        let likes = [like(1, 10), like(2, 10), like(1, 11), like(2, 12)];
+        let data = InteractionData {
+            user_ids: vec![1, 2],
+            posts: [10, 11, 12, 13]
+                .into_iter()
+                .map(|id| PostTimeModel {
+                    id,
+                    created_at: NaiveDateTime::default(),
+                })
+                .collect(),
+            observed: likes
+                .iter()
+                .map(|l| liked_row(l.user_id, l.post_id))
+                .collect(),
+        };
+        EvaluationData::split(data, &likes, 2, 3)
+    }
+
+    #[test]
+    fn split_removes_held_out_and_later_pairs_from_train() {
+        let data = evaluation_data();
+        let mut observed = data
+            .train
+            .observed
+            .iter()
+            .map(|row| (row.user_id, row.post_id))
+            .collect::<Vec<_>>();
+        observed.sort_unstable();
+        assert_eq!(observed, vec![(1, 10), (2, 10)]);
+        assert_eq!(data.test_likes, HashMap::from([(1, HashSet::from([11]))]));
+        assert_eq!(data.held_out_likes(), 1);
+    }
+
+    #[test]
+    fn ideal_dcg_counts_at_most_k_relevant_posts() {
+        let data = evaluation_data();
+        // the held out post first, then the rest by id
+        let metrics = data.rank(3, |_, candidates| {
+            candidates
+                .iter()
+                .map(|&post_id| {
+                    if post_id == 11 {
+                        1.0
+                    } else {
+                        -(post_id as f32)
+                    }
+                })
+                .collect()
+        });
+        assert_eq!(metrics.ndcg_at_k, 1.0);
+        assert_eq!(metrics.recall_at_k, 1.0);
+        assert!((metrics.precision_at_k - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn coverage_is_over_all_posts() {
+        let data = evaluation_data();
+        let metrics = data.rank(2, |_, candidates| {
+            candidates.iter().map(|&post_id| post_id as f32).collect()
+        });
+        // user 1 is recommended 13 and 12 out of the 4 posts
+        assert_eq!(metrics.coverage, 0.5);
+        assert_eq!(metrics.ndcg_at_k, 0.0);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{TimeDelta, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    libs::env::env_or,
+    models::recommender_snapshot_model::{get_latest_snapshot, save_snapshot},
+    services::{
+        ndarray::{load_models, RecommenderSnapshot},
+        recommenders::{load_recommender_model, Recommender, RecommenderConfig, RecommenderKind},
+    },
+};
+
+pub type SnapshotResult<T> = Result<T, SnapshotError>;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    IO(String),
+    Serde(String),
+    Sqlx(String),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::IO(e) => write!(f, "io error: {}", e),
+            SnapshotError::Serde(e) => write!(f, "invalid snapshot: {}", e),
+            SnapshotError::Sqlx(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(value: std::io::Error) -> Self {
+        SnapshotError::IO(value.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(value: serde_json::Error) -> Self {
+        SnapshotError::Serde(value.to_string())
+    }
+}
+
+impl From<lib_models::error::ModelError> for SnapshotError {
+    fn from(value: lib_models::error::ModelError) -> Self {
+        SnapshotError::Sqlx(format!("{:?}", value))
+    }
+}
+
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+/// Number of snapshots kept in Postgres, older ones are deleted as new ones are saved.
+const POSTGRES_SNAPSHOTS_KEPT: i64 = 3;
+
+/// Where recommender snapshots are saved.
+#[derive(Debug, Clone)]
+pub enum SnapshotStore {
+    File(PathBuf),
+    Postgres(PgPool),
+    Disabled,
+}
+
+impl SnapshotStore {
+    /// RECOMMENDER_SNAPSHOT_STORE=file|postgres|none, with the file at RECOMMENDER_SNAPSHOT_PATH.
+    pub fn from_env(pool: &PgPool) -> Self {
+        match env_or("RECOMMENDER_SNAPSHOT_STORE", "file".to_string()).as_str() {
+            "file" => SnapshotStore::File(PathBuf::from(env_or(
+                "RECOMMENDER_SNAPSHOT_PATH",
+                "recommender_snapshot.json".to_string(),
+            ))),
+            "postgres" => SnapshotStore::Postgres(pool.clone()),
+            "none" => SnapshotStore::Disabled,
+            other => panic!("Invalid RECOMMENDER_SNAPSHOT_STORE: {}", other),
+        }
+    }
+
+    /// The newest snapshot in the current format, None if there is none.
+    pub async fn load(&self) -> SnapshotResult<Option<RecommenderSnapshot>> {
+        let data = match self {
+            SnapshotStore::File(path) => match fs::read(path) {
+                Ok(data) => Some(data),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            },
+            SnapshotStore::Postgres(pool) => {
+                get_latest_snapshot(pool, RecommenderSnapshot::VERSION as i32).await?
+            }
+            SnapshotStore::Disabled => None,
+        };
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        // older formats may not deserialize at all, so the version is checked first
+        let version = serde_json::from_slice::<SnapshotVersion>(&data)?.version;
+        if version != RecommenderSnapshot::VERSION {
+            return Ok(None);
+        }
+        let snapshot = serde_json::from_slice::<RecommenderSnapshot>(&data)?;
+        Ok(Some(snapshot))
+    }
+
+    pub async fn save(&self, snapshot: &RecommenderSnapshot) -> SnapshotResult<()> {
+        match self {
+            SnapshotStore::File(path) => {
+                let data = serde_json::to_vec(snapshot)?;
+                // written beside the old snapshot and renamed over it, so a crash never leaves a partial file
+                let tmp_path = path.with_extension("tmp");
+                fs::write(&tmp_path, data)?;
+                fs::rename(&tmp_path, path)?;
+            }
+            SnapshotStore::Postgres(pool) => {
+                let data = serde_json::to_vec(snapshot)?;
+                save_snapshot(
+                    pool,
+                    snapshot.version as i32,
+                    &data,
+                    POSTGRES_SNAPSHOTS_KEPT,
+                )
+                .await?;
+            }
+            SnapshotStore::Disabled => {}
+        }
+        Ok(())
+    }
+}
+
+/// Builds the recommender of the configured kind. Matrix factorization starts from the latest snapshot and only
+/// trains the users and posts added since. It retrains fully when there is no usable snapshot, it is older than
+/// RECOMMENDER_SNAPSHOT_MAX_AGE_HOURS, or it was trained with different hyperparameters.
+pub async fn load_recommender(
+    pool: &PgPool,
+    config: RecommenderConfig,
+    store: &SnapshotStore,
+) -> Box<dyn Recommender> {
+    if config.kind != RecommenderKind::MatrixFactorization {
+        let mut model = load_recommender_model(pool, config)
+            .await
+            .expect("Could not query for interactions matrix model");
+        model.fit();
+        return model;
+    }
+
+    let mut ndarray_app_state = load_models(pool, config.clone())
+        .await
+        .expect("Could not query for interactions matrix model");
+
+    let snapshot = store.load().await.unwrap_or_else(|e| {
+        println!("Could not load recommender snapshot: {}", e);
+        None
+    });
+    // embeddings with a different number of features cannot be reused at all
+    let snapshot = snapshot.filter(|s| s.config.k_features == config.k_features);
+
+    match snapshot {
+        Some(snapshot) => {
+            let max_age = TimeDelta::hours(env_or("RECOMMENDER_SNAPSHOT_MAX_AGE_HOURS", 24));
+            let stale = Utc::now() - snapshot.trained_at > max_age;
+            let (new_users, new_posts) = ndarray_app_state.apply_snapshot(&snapshot);
+            if stale || snapshot.config != config {
+                // the snapshot is still a better starting point than random embeddings
+                ndarray_app_state.train();
+            } else {
+                ndarray_app_state.train_rows(&new_users, &new_posts);
+            }
+        }
+        None => ndarray_app_state.train(),
+    }
+
+    if let Err(e) = store.save(&ndarray_app_state.snapshot()).await {
+        println!("Could not save recommender snapshot: {}", e);
+    }
+
+    Box::new(ndarray_app_state)
+}
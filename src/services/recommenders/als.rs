@@ -0,0 +1,293 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use ndarray::{Array1, Array2};
+use ndarray_rand::RandomExt;
+use rand::distributions::Uniform;
+
+use super::{InteractionData, Recommender, RecommenderConfig, SharedRows};
+use crate::services::observation_channel::ChannelKind;
+
+/// Implicit feedback matrix factorization (Hu, Koren and Volinsky) trained with alternating least squares.
+/// Every pair is a preference of 1 if it has a positive signal and 0 otherwise, trusted with a confidence of
+/// `1 + als_confidence * strength`, so unobserved pairs count as weak negatives without sampling them.
+#[derive(Debug, Clone)]
+pub struct Als {
+    config: RecommenderConfig,
+    user_index: Arc<HashMap<i64, usize>>,
+    post_index: Arc<HashMap<i64, usize>>,
+    /// The signals of each observed post index, by user index.
+    signals: SharedRows<HashMap<usize, Vec<f32>>>,
+    /// Implicit strength of the pairs with a positive signal, by user index and by post index.
+    user_strengths: SharedRows<HashMap<usize, f32>>,
+    post_strengths: SharedRows<HashMap<usize, f32>>,
+    user_factors: Array2<f32>,
+    post_factors: Array2<f32>,
+    /// Observed since the last refresh, their factors are solved again.
+    changed_users: HashSet<usize>,
+    changed_posts: HashSet<usize>,
+}
+
+impl Als {
+    pub fn new(config: RecommenderConfig, data: &InteractionData) -> Self {
+        let k = config.k_features;
+        let n_users = data.user_ids.len();
+        let n_posts = data.posts.len();
+        let mut als = Self {
+            user_index: Arc::new(
+                data.user_ids
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, &id)| (id, idx))
+                    .collect(),
+            ),
+            post_index: Arc::new(
+                data.posts
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, p)| (p.id, idx))
+                    .collect(),
+            ),
+            signals: SharedRows::new(vec![HashMap::new(); n_users]),
+            user_strengths: SharedRows::new(vec![HashMap::new(); n_users]),
+            post_strengths: SharedRows::new(vec![HashMap::new(); n_posts]),
+            user_factors: Array2::random((n_users, k), Uniform::new(0.0, 0.1)),
+            post_factors: Array2::random((n_posts, k), Uniform::new(0.0, 0.1)),
+            changed_users: HashSet::new(),
+            changed_posts: HashSet::new(),
+            config,
+        };
+        for row in &data.observed {
+            // users or posts created between the queries are picked up by the next load
+            let (Some(&u_idx), Some(&p_idx)) = (
+                als.user_index.get(&row.user_id),
+                als.post_index.get(&row.post_id),
+            ) else {
+                continue;
+            };
+            let signals = als.config.signals(row);
+            als.set_signals(u_idx, p_idx, signals);
+        }
+        als
+    }
+
+    fn set_signals(&mut self, u_idx: usize, p_idx: usize, signals: Vec<f32>) {
+        let strength = self.config.implicit_strength(&signals);
+        if strength > 0.0 {
+            self.user_strengths.get_mut(u_idx).insert(p_idx, strength);
+            self.post_strengths.get_mut(p_idx).insert(u_idx, strength);
+        } else if self.user_strengths[u_idx].contains_key(&p_idx) {
+            self.user_strengths.get_mut(u_idx).remove(&p_idx);
+            self.post_strengths.get_mut(p_idx).remove(&u_idx);
+        }
+        self.signals.get_mut(u_idx).insert(p_idx, signals);
+    }
+
+    /// Solves the factors of `rows` with the factors of the other side fixed.
+    fn solve(
+        config: &RecommenderConfig,
+        fixed: &Array2<f32>,
+        strengths: &SharedRows<HashMap<usize, f32>>,
+        target: &mut Array2<f32>,
+        rows: impl Iterator<Item = usize>,
+    ) {
+        let k = config.k_features;
+        let fixed = fixed.mapv(|v| v as f64);
+        // the unobserved pairs all have confidence 1, so their part of the system is shared
+        let mut gram = fixed.t().dot(&fixed);
+        for i in 0..k {
+            gram[(i, i)] += config.lambda as f64;
+        }
+
+        for row in rows {
+            let mut a = gram.clone();
+            let mut b = Array1::<f64>::zeros(k);
+            for (&other, &strength) in &strengths[row] {
+                let confidence = 1.0 + config.als_confidence as f64 * strength as f64;
+                let y = fixed.row(other);
+                for i in 0..k {
+                    for j in 0..k {
+                        a[(i, j)] += (confidence - 1.0) * y[i] * y[j];
+                    }
+                    b[i] += confidence * y[i];
+                }
+            }
+            let x = solve_spd(a, b);
+            target.row_mut(row).assign(&x.mapv(|v| v as f32));
+        }
+    }
+}
+
+/// Solves `a x = b` for a symmetric positive definite `a` with a Cholesky decomposition.
+fn solve_spd(a: Array2<f64>, b: Array1<f64>) -> Array1<f64> {
+    let n = b.len();
+    let mut l = Array2::<f64>::zeros((n, n));
+    for j in 0..n {
+        let diagonal = a[(j, j)] - (0..j).map(|k| l[(j, k)].powi(2)).sum::<f64>();
+        // guards against a lambda of 0 making the system singular
+        l[(j, j)] = diagonal.max(1e-9).sqrt();
+        for i in j + 1..n {
+            let sum = (0..j).map(|k| l[(i, k)] * l[(j, k)]).sum::<f64>();
+            l[(i, j)] = (a[(i, j)] - sum) / l[(j, j)];
+        }
+    }
+
+    let mut y = Array1::<f64>::zeros(n);
+    for i in 0..n {
+        let sum = (0..i).map(|k| l[(i, k)] * y[k]).sum::<f64>();
+        y[i] = (b[i] - sum) / l[(i, i)];
+    }
+    let mut x = Array1::<f64>::zeros(n);
+    for i in (0..n).rev() {
+        let sum = (i + 1..n).map(|k| l[(k, i)] * x[k]).sum::<f64>();
+        x[i] = (y[i] - sum) / l[(i, i)];
+    }
+    x
+}
+
+impl Recommender for Als {
+    fn fit(&mut self) {
+        let n_users = self.user_strengths.len();
+        let n_posts = self.post_strengths.len();
+        for _ in 0..self.config.epochs {
+            Self::solve(
+                &self.config,
+                &self.post_factors,
+                &self.user_strengths,
+                &mut self.user_factors,
+                0..n_users,
+            );
+            Self::solve(
+                &self.config,
+                &self.user_factors,
+                &self.post_strengths,
+                &mut self.post_factors,
+                0..n_posts,
+            );
+        }
+        self.changed_users.clear();
+        self.changed_posts.clear();
+    }
+
+    fn score(&self, user_id: i64, post_ids: &[i64]) -> Vec<Option<f32>> {
+        let Some(&u_idx) = self.user_index.get(&user_id) else {
+            return vec![None; post_ids.len()];
+        };
+        let user = self.user_factors.row(u_idx);
+        post_ids
+            .iter()
+            .map(|post_id| {
+                let &p_idx = self.post_index.get(post_id)?;
+                Some(user.dot(&self.post_factors.row(p_idx)))
+            })
+            .collect()
+    }
+
+    /// New users and posts have zero factors and score 0 until they are observed.
+    fn add_user(&mut self, user_id: i64) {
+        if self.user_index.contains_key(&user_id) {
+            return;
+        }
+        let zeros = Array1::<f32>::zeros(self.config.k_features);
+        if let Err(e) = self.user_factors.push_row(zeros.view()) {
+            println!("Could not add user {} to the recommender: {}", user_id, e);
+            return;
+        }
+        Arc::make_mut(&mut self.user_index).insert(user_id, self.user_strengths.len());
+        self.user_strengths.push(HashMap::new());
+        self.signals.push(HashMap::new());
+    }
+
+    fn add_post(&mut self, post_id: i64) {
+        if self.post_index.contains_key(&post_id) {
+            return;
+        }
+        let zeros = Array1::<f32>::zeros(self.config.k_features);
+        if let Err(e) = self.post_factors.push_row(zeros.view()) {
+            println!("Could not add post {} to the recommender: {}", post_id, e);
+            return;
+        }
+        Arc::make_mut(&mut self.post_index).insert(post_id, self.post_strengths.len());
+        self.post_strengths.push(HashMap::new());
+    }
+
+    /// The factor row is zeroed and left unused until the next full load.
+    fn remove_user(&mut self, user_id: i64) {
+        let Some(u_idx) = Arc::make_mut(&mut self.user_index).remove(&user_id) else {
+            return;
+        };
+        for (p_idx, _) in std::mem::take(self.user_strengths.get_mut(u_idx)) {
+            self.post_strengths.get_mut(p_idx).remove(&u_idx);
+            self.changed_posts.insert(p_idx);
+        }
+        std::mem::take(self.signals.get_mut(u_idx));
+        self.user_factors.row_mut(u_idx).fill(0.0);
+        self.changed_users.remove(&u_idx);
+    }
+
+    fn remove_post(&mut self, post_id: i64) {
+        let Some(p_idx) = Arc::make_mut(&mut self.post_index).remove(&post_id) else {
+            return;
+        };
+        for (u_idx, _) in std::mem::take(self.post_strengths.get_mut(p_idx)) {
+            self.user_strengths.get_mut(u_idx).remove(&p_idx);
+            self.changed_users.insert(u_idx);
+        }
+        for u_idx in 0..self.signals.len() {
+            if self.signals[u_idx].contains_key(&p_idx) {
+                self.signals.get_mut(u_idx).remove(&p_idx);
+            }
+        }
+        self.post_factors.row_mut(p_idx).fill(0.0);
+        self.changed_posts.remove(&p_idx);
+    }
+
+    fn observe(&mut self, user_id: i64, post_id: i64, kind: ChannelKind, signal: f32) {
+        let Some(observation) = self.config.channel_index(kind) else {
+            return;
+        };
+        let (Some(&u_idx), Some(&p_idx)) =
+            (self.user_index.get(&user_id), self.post_index.get(&post_id))
+        else {
+            return;
+        };
+        let mut signals = self.signals[u_idx]
+            .get(&p_idx)
+            .cloned()
+            .unwrap_or_else(|| vec![0.0; self.config.channels.len()]);
+        signals[observation] = signal;
+        self.set_signals(u_idx, p_idx, signals);
+        self.changed_users.insert(u_idx);
+        self.changed_posts.insert(p_idx);
+    }
+
+    /// Solves the changed users against the current posts, then the changed posts against the updated users.
+    fn refresh(&mut self) {
+        let users = std::mem::take(&mut self.changed_users);
+        let posts = std::mem::take(&mut self.changed_posts);
+        if !users.is_empty() {
+            Self::solve(
+                &self.config,
+                &self.post_factors,
+                &self.user_strengths,
+                &mut self.user_factors,
+                users.into_iter(),
+            );
+        }
+        if !posts.is_empty() {
+            Self::solve(
+                &self.config,
+                &self.user_factors,
+                &self.post_strengths,
+                &mut self.post_factors,
+                posts.into_iter(),
+            );
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Recommender> {
+        Box::new(self.clone())
+    }
+}
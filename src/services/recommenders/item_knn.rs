@@ -0,0 +1,176 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use super::{InteractionData, Recommender, RecommenderConfig};
+use crate::services::observation_channel::ChannelKind;
+
+/// Item-item kNN on the like matrix.
+/// Two posts are similar by the cosine of their likers, `|likers of a and b| / sqrt(|likers of a| * |likers of b|)`,
+/// and a post scores the summed similarity to the posts the user liked among its `knn_neighbors` most similar.
+#[derive(Debug, Clone)]
+pub struct ItemKnn {
+    config: RecommenderConfig,
+    likes_by_user: Arc<HashMap<i64, HashSet<i64>>>,
+    likers_by_post: Arc<HashMap<i64, HashSet<i64>>>,
+    neighbors: Arc<HashMap<i64, Vec<(i64, f32)>>>,
+    /// Posts liked or unliked since the last refresh, whose neighbors are out of date.
+    changed_posts: HashSet<i64>,
+}
+
+impl ItemKnn {
+    pub fn new(config: RecommenderConfig, data: &InteractionData) -> Self {
+        let mut likes_by_user = HashMap::<i64, HashSet<i64>>::new();
+        let mut likers_by_post = HashMap::<i64, HashSet<i64>>::new();
+        for &user_id in &data.user_ids {
+            likes_by_user.entry(user_id).or_default();
+        }
+        for post in &data.posts {
+            likers_by_post.entry(post.id).or_default();
+        }
+        for row in data.observed.iter().filter(|row| row.is_liked == 1) {
+            // users or posts created between the queries are picked up by the next load
+            if let (Some(likes), Some(likers)) = (
+                likes_by_user.get_mut(&row.user_id),
+                likers_by_post.get_mut(&row.post_id),
+            ) {
+                likes.insert(row.post_id);
+                likers.insert(row.user_id);
+            }
+        }
+        Self {
+            config,
+            likes_by_user: Arc::new(likes_by_user),
+            likers_by_post: Arc::new(likers_by_post),
+            neighbors: Arc::default(),
+            changed_posts: HashSet::new(),
+        }
+    }
+
+    /// The most similar posts to `post_id` that share a liker with it.
+    fn find_neighbors(&self, post_id: i64) -> Vec<(i64, f32)> {
+        let Some(likers) = self.likers_by_post.get(&post_id) else {
+            return Vec::new();
+        };
+        let mut co_likes = HashMap::<i64, f32>::new();
+        for user_id in likers {
+            for &other in &self.likes_by_user[user_id] {
+                if other != post_id {
+                    *co_likes.entry(other).or_default() += 1.0;
+                }
+            }
+        }
+
+        let mut neighbors = co_likes
+            .into_iter()
+            .map(|(other, co_liked)| {
+                let norm = (likers.len() * self.likers_by_post[&other].len()) as f32;
+                (other, co_liked / norm.sqrt())
+            })
+            .collect::<Vec<_>>();
+        neighbors.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        neighbors.truncate(self.config.knn_neighbors);
+        neighbors
+    }
+}
+
+impl Recommender for ItemKnn {
+    fn fit(&mut self) {
+        self.neighbors = Arc::new(
+            self.likers_by_post
+                .keys()
+                .map(|&post_id| (post_id, self.find_neighbors(post_id)))
+                .collect(),
+        );
+        self.changed_posts.clear();
+    }
+
+    /// Posts added since the last refresh have no neighbors yet and are None.
+    fn score(&self, user_id: i64, post_ids: &[i64]) -> Vec<Option<f32>> {
+        let Some(liked) = self.likes_by_user.get(&user_id) else {
+            return vec![None; post_ids.len()];
+        };
+        post_ids
+            .iter()
+            .map(|post_id| {
+                let neighbors = self.neighbors.get(post_id)?;
+                Some(
+                    neighbors
+                        .iter()
+                        .filter(|(other, _)| liked.contains(other))
+                        .map(|(_, similarity)| similarity)
+                        .sum(),
+                )
+            })
+            .collect()
+    }
+
+    fn add_user(&mut self, user_id: i64) {
+        Arc::make_mut(&mut self.likes_by_user)
+            .entry(user_id)
+            .or_default();
+    }
+
+    fn add_post(&mut self, post_id: i64) {
+        Arc::make_mut(&mut self.likers_by_post)
+            .entry(post_id)
+            .or_default();
+    }
+
+    /// Their likes stop counting towards the similarity of the posts they liked.
+    fn remove_user(&mut self, user_id: i64) {
+        let likes = Arc::make_mut(&mut self.likes_by_user).remove(&user_id);
+        for post_id in likes.unwrap_or_default() {
+            if let Some(likers) = Arc::make_mut(&mut self.likers_by_post).get_mut(&post_id) {
+                likers.remove(&user_id);
+            }
+            self.changed_posts.insert(post_id);
+        }
+    }
+
+    /// Other posts keep it in their neighbors until the next fit, but it is no longer liked by anyone so adds nothing.
+    fn remove_post(&mut self, post_id: i64) {
+        let likers = Arc::make_mut(&mut self.likers_by_post).remove(&post_id);
+        for user_id in likers.unwrap_or_default() {
+            if let Some(likes) = Arc::make_mut(&mut self.likes_by_user).get_mut(&user_id) {
+                likes.remove(&post_id);
+            }
+        }
+        Arc::make_mut(&mut self.neighbors).remove(&post_id);
+        self.changed_posts.remove(&post_id);
+    }
+
+    /// Only likes are used.
+    fn observe(&mut self, user_id: i64, post_id: i64, kind: ChannelKind, signal: f32) {
+        if kind != ChannelKind::Like {
+            return;
+        }
+        let (Some(likes), Some(likers)) = (
+            Arc::make_mut(&mut self.likes_by_user).get_mut(&user_id),
+            Arc::make_mut(&mut self.likers_by_post).get_mut(&post_id),
+        ) else {
+            return;
+        };
+        if signal > 0.0 {
+            likes.insert(post_id);
+            likers.insert(user_id);
+        } else {
+            likes.remove(&post_id);
+            likers.remove(&user_id);
+        }
+        self.changed_posts.insert(post_id);
+    }
+
+    /// Recomputes the neighbors of the changed posts, the lists of other posts catch up at the next fit.
+    fn refresh(&mut self) {
+        for post_id in std::mem::take(&mut self.changed_posts) {
+            let neighbors = self.find_neighbors(post_id);
+            Arc::make_mut(&mut self.neighbors).insert(post_id, neighbors);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Recommender> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,273 @@
+use std::{fmt::Debug, ops::Index, str::FromStr, sync::Arc};
+
+use lib_models::error::ModelResult;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    libs::env::env_or,
+    models::interactions_matrix_model::{
+        build_model, get_posts, get_user_ids, InteractionsMatrixModel, PostTimeModel,
+    },
+    services::{
+        ndarray::{build_models, RecommenderSnapshot},
+        observation_channel::{ChannelKind, ObservationChannel},
+    },
+};
+
+pub mod als;
+pub mod item_knn;
+pub mod popularity;
+
+/// Scores posts for users.
+/// The background task in `services::recommender` clones the published model, applies changes to the clone
+/// and publishes it, so `score` never sees a partly applied change.
+/// Models keep their interactions and index maps behind `Arc`, e.g. in `SharedRows`, so that the clone
+/// only copies what the changes touch.
+pub trait Recommender: Debug + Send + Sync {
+    /// Trains on everything the model was built with or has observed since.
+    fn fit(&mut self);
+
+    /// One score per post in `post_ids`, higher is better.
+    /// None for posts the model cannot score for the user, e.g. when either was added since the last publish.
+    fn score(&self, user_id: i64, post_ids: &[i64]) -> Vec<Option<f32>>;
+
+    /// Adds a user with no interactions, ignored if the user is already known.
+    fn add_user(&mut self, user_id: i64);
+
+    /// Adds a post with no interactions, ignored if the post is already known.
+    fn add_post(&mut self, post_id: i64);
+
+    /// Forgets a deleted user and their interactions, ignored if the user is unknown.
+    fn remove_user(&mut self, user_id: i64);
+
+    /// Forgets a deleted or hidden post and its interactions, ignored if the post is unknown.
+    fn remove_post(&mut self, post_id: i64);
+
+    /// Sets the signal of one channel of a user and post, e.g. `ChannelKind::Like` to 1 when liked and 0 when unliked.
+    /// Ignored if the user or post is unknown.
+    fn observe(&mut self, user_id: i64, post_id: i64, kind: ChannelKind, signal: f32);
+
+    /// Cheaply updates the model for what was added and observed since the last `refresh` or `fit`.
+    fn refresh(&mut self);
+
+    fn clone_box(&self) -> Box<dyn Recommender>;
+
+    /// Reuses what the previous model learned before a `fit`, where the model supports it.
+    fn warm_start(&mut self, _previous: &dyn Recommender) {}
+
+    /// The learned state to save between restarts, None for models that are cheap to fit.
+    fn snapshot(&self) -> Option<RecommenderSnapshot> {
+        None
+    }
+}
+
+/// Rows by user or post index, shared between a model and its clones until changed.
+/// Changing a row copies the list of rows the first time and then only that row.
+#[derive(Debug)]
+pub struct SharedRows<T>(Arc<Vec<Arc<T>>>);
+
+impl<T> Clone for SharedRows<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Default for SharedRows<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<T: Clone> SharedRows<T> {
+    pub fn new(rows: Vec<T>) -> Self {
+        Self(Arc::new(rows.into_iter().map(Arc::new).collect()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().map(|row| row.as_ref())
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> &mut T {
+        Arc::make_mut(&mut Arc::make_mut(&mut self.0)[idx])
+    }
+
+    pub fn push(&mut self, row: T) {
+        Arc::make_mut(&mut self.0).push(Arc::new(row));
+    }
+}
+
+impl<T> Index<usize> for SharedRows<T> {
+    type Output = T;
+
+    fn index(&self, idx: usize) -> &T {
+        &self.0[idx]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommenderKind {
+    /// SGD matrix factorization, see `NDArrayAppState`.
+    MatrixFactorization,
+    /// The same posts for everyone, by recent interactions.
+    Popularity,
+    /// Posts liked by the users who liked the same posts as the user.
+    ItemKnn,
+    /// Implicit feedback matrix factorization trained with alternating least squares.
+    Als,
+}
+
+impl FromStr for RecommenderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "matrix_factorization" | "mf" => Ok(RecommenderKind::MatrixFactorization),
+            "popularity" => Ok(RecommenderKind::Popularity),
+            "item_knn" => Ok(RecommenderKind::ItemKnn),
+            "als" => Ok(RecommenderKind::Als),
+            other => Err(format!("unknown recommender {}", other)),
+        }
+    }
+}
+
+/// Recommender hyperparameters, each model uses the ones that apply to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecommenderConfig {
+    pub kind: RecommenderKind,
+    /// Number of latent features in each embedding.
+    pub k_features: usize,
+    /// The interactions observed for every user and post pair, in the order they are stored.
+    pub channels: Vec<ObservationChannel>,
+    /// Learning rate.
+    pub alpha: f32,
+    /// Regularization.
+    pub lambda: f32,
+    /// Passes over the data, ALS iterations for `Als`.
+    pub epochs: usize,
+    /// Unobserved posts sampled as negatives per observed post of each user.
+    pub negative_ratio: usize,
+    /// SGD passes over each pair observed online, e.g. when a post is liked.
+    pub online_steps: usize,
+    /// How much more an observed pair is trusted per unit of signal in ALS.
+    pub als_confidence: f32,
+    /// Most similar posts kept per post in item kNN.
+    pub knn_neighbors: usize,
+    /// How fast popularity decays with the age of a post, 0 ranks by all time popularity.
+    pub trending_gravity: f32,
+}
+
+impl Default for RecommenderConfig {
+    fn default() -> Self {
+        Self {
+            kind: RecommenderKind::MatrixFactorization,
+            k_features: 10,
+            channels: ObservationChannel::defaults(),
+            alpha: 0.05,
+            lambda: 0.1,
+            epochs: 10,
+            negative_ratio: 4,
+            online_steps: 3,
+            als_confidence: 40.0,
+            knn_neighbors: 50,
+            trending_gravity: 1.5,
+        }
+    }
+}
+
+impl RecommenderConfig {
+    /// RECOMMENDER=matrix_factorization|popularity|item_knn|als picks the model.
+    /// See `ObservationChannel::parse_list` for the format of RECOMMENDER_CHANNELS.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let kind = match std::env::var("RECOMMENDER") {
+            Ok(kind) => kind
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid RECOMMENDER: {}", e)),
+            Err(_) => default.kind,
+        };
+        let channels = match std::env::var("RECOMMENDER_CHANNELS") {
+            Ok(channels) => ObservationChannel::parse_list(&channels)
+                .unwrap_or_else(|e| panic!("Invalid RECOMMENDER_CHANNELS: {}", e)),
+            Err(_) => default.channels,
+        };
+        Self {
+            kind,
+            k_features: env_or("RECOMMENDER_K_FEATURES", default.k_features),
+            channels,
+            alpha: env_or("RECOMMENDER_ALPHA", default.alpha),
+            lambda: env_or("RECOMMENDER_LAMBDA", default.lambda),
+            epochs: env_or("RECOMMENDER_EPOCHS", default.epochs),
+            negative_ratio: env_or("RECOMMENDER_NEGATIVE_RATIO", default.negative_ratio),
+            online_steps: env_or("RECOMMENDER_ONLINE_STEPS", default.online_steps),
+            als_confidence: env_or("RECOMMENDER_ALS_CONFIDENCE", default.als_confidence),
+            knn_neighbors: env_or("RECOMMENDER_KNN_NEIGHBORS", default.knn_neighbors),
+            trending_gravity: env_or("RECOMMENDER_TRENDING_GRAVITY", default.trending_gravity),
+        }
+    }
+
+    /// The index of the channel in each pair's observations, None if the channel is not used.
+    pub fn channel_index(&self, kind: ChannelKind) -> Option<usize> {
+        self.channels.iter().position(|c| c.kind == kind)
+    }
+
+    /// The combined strength of the positive signals of a pair, 0 if there are none.
+    pub fn implicit_strength(&self, signals: &[f32]) -> f32 {
+        self.channels
+            .iter()
+            .zip(signals)
+            .map(|(channel, &signal)| channel.implicit_strength(signal))
+            .sum()
+    }
+
+    /// The signals of a pair in channel order.
+    pub fn signals(&self, row: &InteractionsMatrixModel) -> Vec<f32> {
+        self.channels.iter().map(|c| c.kind.signal(row)).collect()
+    }
+}
+
+/// Everything the recommenders are built from.
+#[derive(Debug, Default, Clone)]
+pub struct InteractionData {
+    pub user_ids: Vec<i64>,
+    pub posts: Vec<PostTimeModel>,
+    pub observed: Vec<InteractionsMatrixModel>,
+}
+
+impl InteractionData {
+    pub async fn load(pool: &PgPool) -> ModelResult<Self> {
+        Ok(Self {
+            user_ids: get_user_ids(pool).await?,
+            posts: get_posts(pool).await?,
+            observed: build_model(pool).await?,
+        })
+    }
+
+    pub fn post_ids(&self) -> Vec<i64> {
+        self.posts.iter().map(|p| p.id).collect()
+    }
+}
+
+/// An untrained recommender of the kind in `config`.
+pub fn new_recommender(config: RecommenderConfig, data: &InteractionData) -> Box<dyn Recommender> {
+    match config.kind {
+        RecommenderKind::MatrixFactorization => Box::new(build_models(config, data)),
+        RecommenderKind::Popularity => Box::new(popularity::Popularity::new(config, data)),
+        RecommenderKind::ItemKnn => Box::new(item_knn::ItemKnn::new(config, data)),
+        RecommenderKind::Als => Box::new(als::Als::new(config, data)),
+    }
+}
+
+/// An untrained recommender of all current interactions.
+pub async fn load_recommender_model(
+    pool: &PgPool,
+    config: RecommenderConfig,
+) -> ModelResult<Box<dyn Recommender>> {
+    let data = InteractionData::load(pool).await?;
+    Ok(new_recommender(config, &data))
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{NaiveDateTime, Utc};
+
+use super::{InteractionData, Recommender, RecommenderConfig};
+use crate::services::observation_channel::ChannelKind;
+
+/// Ranks posts the same for every user, by their total interaction strength divided by
+/// `(age in hours + 2) ^ trending_gravity`, so that newer posts need fewer interactions to rank high.
+#[derive(Debug, Clone)]
+pub struct Popularity {
+    config: RecommenderConfig,
+    /// The signals of each user and post pair, kept so that an unlike can be subtracted.
+    signals: Arc<HashMap<(i64, i64), Vec<f32>>>,
+    totals: HashMap<i64, f32>,
+    created_at: Arc<HashMap<i64, NaiveDateTime>>,
+}
+
+impl Popularity {
+    pub fn new(config: RecommenderConfig, data: &InteractionData) -> Self {
+        let mut signals = HashMap::new();
+        for row in &data.observed {
+            signals.insert((row.user_id, row.post_id), config.signals(row));
+        }
+        let created_at = data.posts.iter().map(|p| (p.id, p.created_at)).collect();
+        Self {
+            config,
+            signals: Arc::new(signals),
+            totals: HashMap::new(),
+            created_at: Arc::new(created_at),
+        }
+    }
+}
+
+impl Recommender for Popularity {
+    fn fit(&mut self) {
+        self.totals.clear();
+        for ((_, post_id), signals) in self.signals.iter() {
+            *self.totals.entry(*post_id).or_default() += self.config.implicit_strength(signals);
+        }
+    }
+
+    /// Scores users the model does not know too, only unknown posts are None.
+    fn score(&self, _user_id: i64, post_ids: &[i64]) -> Vec<Option<f32>> {
+        let now = Utc::now().naive_utc();
+        post_ids
+            .iter()
+            .map(|post_id| {
+                let created_at = self.created_at.get(post_id)?;
+                let total = self.totals.get(post_id).copied().unwrap_or_default();
+                let age_hours = (now - *created_at).num_minutes().max(0) as f32 / 60.0;
+                Some(total / (age_hours + 2.0).powf(self.config.trending_gravity))
+            })
+            .collect()
+    }
+
+    fn add_user(&mut self, _user_id: i64) {}
+
+    fn add_post(&mut self, post_id: i64) {
+        Arc::make_mut(&mut self.created_at)
+            .entry(post_id)
+            .or_insert_with(|| Utc::now().naive_utc());
+    }
+
+    fn remove_user(&mut self, user_id: i64) {
+        let config = &self.config;
+        let totals = &mut self.totals;
+        Arc::make_mut(&mut self.signals).retain(|&(u_id, post_id), signals| {
+            if u_id != user_id {
+                return true;
+            }
+            if let Some(total) = totals.get_mut(&post_id) {
+                *total -= config.implicit_strength(signals);
+            }
+            false
+        });
+    }
+
+    fn remove_post(&mut self, post_id: i64) {
+        Arc::make_mut(&mut self.created_at).remove(&post_id);
+        self.totals.remove(&post_id);
+        Arc::make_mut(&mut self.signals).retain(|&(_, p_id), _| p_id != post_id);
+    }
+
+    fn observe(&mut self, user_id: i64, post_id: i64, kind: ChannelKind, signal: f32) {
+        let Some(observation) = self.config.channel_index(kind) else {
+            return;
+        };
+        if !self.created_at.contains_key(&post_id) {
+            return;
+        }
+        let signals = Arc::make_mut(&mut self.signals)
+            .entry((user_id, post_id))
+            .or_insert_with(|| vec![0.0; self.config.channels.len()]);
+        let before = self.config.implicit_strength(signals);
+        signals[observation] = signal;
+        let after = self.config.implicit_strength(signals);
+        *self.totals.entry(post_id).or_default() += after - before;
+    }
+
+    /// Totals are kept up to date by `observe`.
+    fn refresh(&mut self) {}
+
+    fn clone_box(&self) -> Box<dyn Recommender> {
+        Box::new(self.clone())
+    }
+}
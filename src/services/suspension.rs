@@ -0,0 +1,27 @@
+use lib_routes::error::{RouteError, RouterResult};
+use sqlx::PgPool;
+
+use crate::models::user_model::{get_ctx_user, CtxUserModel};
+
+/// The error a suspended user is refused with, None if they are not suspended.
+pub fn suspension_error(user: &CtxUserModel) -> Option<RouteError> {
+    if !user.suspended {
+        return None;
+    }
+    Some(RouteError::AccountSuspended {
+        until: user.suspended_until,
+        reason: user.suspension_reason.clone(),
+    })
+}
+
+/// Errs with AccountSuspended if the user's suspension is in effect.
+/// Should only be called once the user has proven who they are, so that suspensions are not revealed to others.
+pub async fn check_not_suspended(pool: &PgPool, username: &str) -> RouterResult<()> {
+    let Some(user) = get_ctx_user(username, pool).await? else {
+        return Ok(());
+    };
+    match suspension_error(&user) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,98 @@
+use lib_hash::{hash_scheme::HashScheme, pepper};
+use lib_routes::error::RouterResult;
+use lib_totp::Totp;
+use rand::{distributions::Alphanumeric, Rng};
+use sqlx::PgPool;
+
+use crate::models::{
+    recovery_code_model::{get_unused_recovery_codes, use_recovery_code, CreateRecoveryCodeModel},
+    totp_model::{get_totp, use_totp_step},
+};
+
+pub const NUM_RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+fn now_unix() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// Recovery codes are compared case insensitively and without the separating dash.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Generates one-time recovery codes, returning the plain codes to show the user once and their hashes to store.
+pub fn generate_recovery_codes(
+    username: &str,
+) -> RouterResult<(Vec<String>, Vec<CreateRecoveryCodeModel>)> {
+    let pepper = pepper::current();
+    let hasher = HashScheme::CURRENT.hasher();
+    let mut codes = Vec::with_capacity(NUM_RECOVERY_CODES);
+    let mut models = Vec::with_capacity(NUM_RECOVERY_CODES);
+
+    for _ in 0..NUM_RECOVERY_CODES {
+        let code = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(RECOVERY_CODE_LEN)
+            .map(|c| (c as char).to_ascii_lowercase())
+            .collect::<String>();
+        let (code_hash, code_salt) = hasher.hash_peppered(&code, pepper)?;
+        models.push(CreateRecoveryCodeModel {
+            username: username.to_string(),
+            code_hash,
+            code_salt,
+            hash_scheme: HashScheme::CURRENT,
+            pepper_id: pepper.map(|p| p.id()),
+        });
+        codes.push(format!(
+            "{}-{}",
+            &code[..RECOVERY_CODE_LEN / 2],
+            &code[RECOVERY_CODE_LEN / 2..]
+        ));
+    }
+
+    Ok((codes, models))
+}
+
+/// Checks a code against the user's totp secret, returning the matched step.
+/// Does not record the step, see `verify_totp_code` for logins.
+pub fn check_totp_code(secret: &str, code: &str) -> RouterResult<Option<u64>> {
+    let totp = Totp::from_base32(secret)?;
+    Ok(totp.verify(code, now_unix())?)
+}
+
+/// Verifies a code against the user's enabled totp secret, rejecting codes that were already used.
+pub async fn verify_totp_code(pool: &PgPool, username: &str, code: &str) -> RouterResult<bool> {
+    let Some(totp) = get_totp(pool, username).await? else {
+        return Ok(false);
+    };
+    if totp.enabled_at.is_none() {
+        return Ok(false);
+    }
+    match check_totp_code(&totp.secret, code)? {
+        Some(step) => Ok(use_totp_step(pool, username, step as i64).await?),
+        None => Ok(false),
+    }
+}
+
+/// Verifies and consumes one of the user's unused recovery codes.
+pub async fn verify_recovery_code(pool: &PgPool, username: &str, code: &str) -> RouterResult<bool> {
+    let code = normalize_recovery_code(code);
+    let stored = get_unused_recovery_codes(pool, username).await?;
+
+    let matched = stored.iter().find(|c| {
+        let pepper = c.pepper_id.and_then(|id| pepper::get(id).ok());
+        c.hash_scheme
+            .hasher()
+            .verify_peppered(&code, pepper, &c.code_salt, &c.code_hash)
+            .is_ok()
+    });
+
+    match matched {
+        Some(c) => Ok(use_recovery_code(pool, c.id).await?),
+        None => Ok(false),
+    }
+}